@@ -1,13 +1,12 @@
 use eframe::egui;
-use std::io;
 use std::time::{Duration, Instant};
-use roboclaw::{Roboclaw, StatusFlags, ConfigFlags, BufferStatus};
-use serialport::{SerialPort, SerialPortType};
+use roboclaw::{Roboclaw, RoboclawError, StatusFlags, Config, ControlMode, BufferStatus, DEFAULT_ADDRESS};
 
 pub struct RoboclawGUI {
     // Connection settings
     port_name: String,
     baud_rate: u32,
+    address: u8,
     connected: bool,
     
     // Motor controls
@@ -19,15 +18,18 @@ pub struct RoboclawGUI {
     // Status displays
     main_battery_voltage: Option<f32>,
     logic_battery_voltage: Option<f32>,
+    pwms: Option<(i16, i16)>,
+    currents: Option<(f32, f32)>,
     encoder_m1: Option<u32>,
     encoder_m2: Option<u32>,
     status_flags: Option<StatusFlags>,
-    config_flags: Option<ConfigFlags>,
+    config: Option<Config>,
     buffer_status: Option<(BufferStatus, BufferStatus)>,
     
     // Control state
     last_update: Instant,
     status_message: String,
+    link_errors: bool,
     
     // Connection state 
     roboclaw: Option<Roboclaw>,
@@ -38,6 +40,7 @@ impl Default for RoboclawGUI {
         Self {
             port_name: "/dev/tty.usbmodem101".to_owned(),
             baud_rate: 38400,
+            address: DEFAULT_ADDRESS,
             connected: false,
             m1_speed: 0.0,
             m2_speed: 0.0,
@@ -45,13 +48,16 @@ impl Default for RoboclawGUI {
             mixed_turn: 0.0,
             main_battery_voltage: None,
             logic_battery_voltage: None,
+            pwms: None,
+            currents: None,
             encoder_m1: None,
             encoder_m2: None,
             status_flags: None,
-            config_flags: None,
+            config: None,
             buffer_status: None,
             last_update: Instant::now(),
             status_message: "Disconnected".to_owned(),
+            link_errors: false,
             roboclaw: None,
         }
     }
@@ -72,7 +78,7 @@ impl RoboclawGUI {
             .ok();
 
         let roboclaw = port
-            .map(|port| Roboclaw::new(port));
+            .map(|port| Roboclaw::with_address(port, self.address));
 
         if let Some(roboclaw) = roboclaw {
             self.roboclaw = Some(roboclaw);
@@ -153,7 +159,7 @@ impl RoboclawGUI {
     fn read_status(&mut self) {
         if let Some(ref mut roboclaw) = self.roboclaw {
             // Reduce polling frequency if we're having communication errors
-            let polling_interval = if self.status_message.contains("crc error") || 
+            let polling_interval = if self.link_errors ||
                                      self.status_message.contains("Failed to read") {
                 Duration::from_millis(2000) // Slower polling when errors occur
             } else {
@@ -180,6 +186,23 @@ impl RoboclawGUI {
                     }
                 }
                 
+                // Read motor outputs
+                if let Ok(pwms) = roboclaw.read_pwms() {
+                    self.pwms = Some(pwms);
+                } else {
+                    if !self.status_message.contains("Motor control error") && !self.status_message.contains("Mixed control error") {
+                        self.status_message = "Failed to read PWMs".to_owned();
+                    }
+                }
+                
+                if let Ok(currents) = roboclaw.read_currents() {
+                    self.currents = Some(currents);
+                } else {
+                    if !self.status_message.contains("Motor control error") && !self.status_message.contains("Mixed control error") {
+                        self.status_message = "Failed to read currents".to_owned();
+                    }
+                }
+                
                 // Read encoders
                 if let Ok((enc1, enc2)) = roboclaw.read_encoders() {
                     self.encoder_m1 = Some(enc1);
@@ -194,13 +217,14 @@ impl RoboclawGUI {
                 match roboclaw.read_error() {
                     Ok(flags) => {
                         self.status_flags = Some(flags);
+                        self.link_errors = false;
                         // If we successfully read status and there are no motor errors in status_message, show "Connected"
                         if !self.status_message.contains("Motor control error") && !self.status_message.contains("Mixed control error") {
                             self.status_message = "Connected".to_owned();
                         }
                     },
                     Err(e) => {
-                        // This is likely where the "crc error" is coming from
+                        self.link_errors = matches!(e, RoboclawError::Crc { .. } | RoboclawError::Timeout);
                         if !self.status_message.contains("Motor control error") && !self.status_message.contains("Mixed control error") {
                             self.status_message = format!("Failed to read error status: {}", e);
                         }
@@ -210,7 +234,7 @@ impl RoboclawGUI {
                 
                 // Read config
                 if let Ok(config) = roboclaw.get_config() {
-                    self.config_flags = Some(config);
+                    self.config = Some(config);
                 } else {
                     if !self.status_message.contains("Motor control error") && !self.status_message.contains("Mixed control error") && !self.status_message.contains("Failed to read error status") {
                         self.status_message = "Failed to read config".to_owned();
@@ -232,48 +256,36 @@ impl RoboclawGUI {
     }
 }
 
-// Helper function to display config flags in a user-friendly way
-fn show_config_flags(ui: &mut egui::Ui, config_flags: &ConfigFlags) {
-    use roboclaw::ConfigFlags;
-
-    ui.heading("Config Flags");
-    ui.label(format!("Raw: 0x{:04X}", config_flags.bits()));
+// Helper function to display the config in a user-friendly way
+#[allow(dead_code)]
+fn show_config(ui: &mut egui::Ui, config: &Config) {
+    match config.control_mode {
+        ControlMode::Rc(options) | ControlMode::Analog(options) => {
+            let name = if matches!(config.control_mode, ControlMode::Rc(_)) { "RC" } else { "Analog" };
+            ui.label(format!("Mode: {}", name));
+            ui.checkbox(&mut options.mixing.clone(), "Mixing");
+            ui.checkbox(&mut options.exponential.clone(), "Exponential");
+            ui.checkbox(&mut options.mcu.clone(), "MCU");
+            ui.checkbox(&mut options.flip_switch.clone(), "Flip Switch");
+        }
+        ControlMode::SimpleSerial { baud_rate } => {
+            ui.label(format!("Mode: Simple Serial at {} baud", baud_rate.bps()));
+        }
+        ControlMode::PacketSerial { baud_rate, address } => {
+            ui.label(format!("Mode: Packet Serial at {} baud, address 0x{:02X}", baud_rate.bps(), address));
+        }
+    }
+    ui.label(format!("Battery Mode: {:?}", config.battery_mode));
 
-    // Show each flag with a checkbox or label
     let flags = [
-        (ConfigFlags::RC_MODE, "RC Mode"),
-        (ConfigFlags::ANALOG_MODE, "Analog Mode"),
-        (ConfigFlags::SIMPLE_SERIAL_MODE, "Simple Serial Mode"),
-        (ConfigFlags::PACKET_SERIAL_MODE, "Packet Serial Mode"),
-        (ConfigFlags::BATTERY_MODE_OFF, "Battery Mode Off"),
-        (ConfigFlags::BATTERY_MODE_AUTO, "Battery Mode Auto"),
-        (ConfigFlags::BATTERY_MODE_2_CELL, "Battery Mode 2 Cell"),
-        (ConfigFlags::BATTERY_MODE_3_CELL, "Battery Mode 3 Cell"),
-        (ConfigFlags::BATTERY_MODE_4_CELL, "Battery Mode 4 Cell"),
-        (ConfigFlags::BATTERY_MODE_5_CELL, "Battery Mode 5 Cell"),
-        (ConfigFlags::BATTERY_MODE_6_CELL, "Battery Mode 6 Cell"),
-        (ConfigFlags::BATTERY_MODE_7_CELL, "Battery Mode 7 Cell"),
-        (ConfigFlags::MIXING, "Mixing"),
-        (ConfigFlags::EXPONENTIAL, "Exponential"),
-        (ConfigFlags::MCU, "MCU"),
-        (ConfigFlags::BAUDRATE_2400, "Baudrate 2400"),
-        (ConfigFlags::BAUDRATE_9600, "Baudrate 9600"),
-        (ConfigFlags::BAUDRATE_19200, "Baudrate 19200"),
-        (ConfigFlags::BAUDRATE_38400, "Baudrate 38400"),
-        (ConfigFlags::BAUDRATE_57600, "Baudrate 57600"),
-        (ConfigFlags::BAUDRATE_115200, "Baudrate 115200"),
-        (ConfigFlags::BAUDRATE_230400, "Baudrate 230400"),
-        (ConfigFlags::BAUDRATE_460800, "Baudrate 460800"),
-        (ConfigFlags::FLIPSWITCH, "Flip Switch"),
-        (ConfigFlags::SLAVE_MODE, "Slave Mode"),
-        (ConfigFlags::RELAY_MODE, "Relay Mode"),
-        (ConfigFlags::SWAP_ENCODERS, "Swap Encoders"),
-        (ConfigFlags::SWAP_BUTTONS, "Swap Buttons"),
-        (ConfigFlags::MULTI_UNIT_MODE, "Multi Unit Mode"),
+        (config.slave_mode, "Slave Mode"),
+        (config.relay_mode, "Relay Mode"),
+        (config.swap_encoders, "Swap Encoders"),
+        (config.swap_buttons, "Swap Buttons"),
+        (config.multi_unit_mode, "Multi Unit Mode"),
     ];
 
-    for (flag, label) in flags.iter() {
-        let enabled = config_flags.contains(*flag);
+    for (enabled, label) in flags.iter() {
         ui.horizontal(|ui| {
             ui.checkbox(&mut enabled.clone(), *label);
         });
@@ -297,6 +309,13 @@ impl eframe::App for RoboclawGUI {
                 ui.text_edit_singleline(&mut self.port_name);
                 ui.label("Baud:");
                 ui.add(egui::DragValue::new(&mut self.baud_rate).speed(100));
+                ui.label("Address:");
+                ui.add_enabled(
+                    !self.connected,
+                    egui::DragValue::new(&mut self.address)
+                        .clamp_range(0x80..=0x87)
+                        .hexadecimal(2, false, true),
+                );
                 
                 if self.connected {
                     if ui.button("Disconnect").clicked() {
@@ -404,7 +423,7 @@ impl eframe::App for RoboclawGUI {
             ui.horizontal(|ui| {
                 ui.group(|ui| {
                     ui.vertical(|ui| {
-                        ui.heading("Battery Status");
+                        ui.heading("Power");
                         if let Some(voltage) = self.main_battery_voltage {
                             ui.label(format!("Main Battery: {:.1}V", voltage));
                         } else {
@@ -416,6 +435,18 @@ impl eframe::App for RoboclawGUI {
                         } else {
                             ui.label("Logic Battery: ---");
                         }
+                        
+                        if let Some((pwm1, pwm2)) = self.pwms {
+                            ui.label(format!("PWM: M1 {:.1}%  M2 {:.1}%", pwm1 as f32 / 327.67, pwm2 as f32 / 327.67));
+                        } else {
+                            ui.label("PWM: ---");
+                        }
+                        
+                        if let Some((current1, current2)) = self.currents {
+                            ui.label(format!("Current: M1 {:.2}A  M2 {:.2}A", current1, current2));
+                        } else {
+                            ui.label("Current: ---");
+                        }
                     });
                 });
                 
@@ -452,6 +483,14 @@ impl eframe::App for RoboclawGUI {
                         if let Some(status) = &self.status_flags {
                             ui.label(format!("Raw Status: 0x{:04X}", status.bits()));
                         }
+
+                        if let Some(roboclaw) = &self.roboclaw {
+                            let stats = roboclaw.retry_stats();
+                            ui.label(format!(
+                                "Link: {} packets, {} retries, {} failed",
+                                stats.packets, stats.retries, stats.failures
+                            ));
+                        }
                         
                         if let Some((buf1, buf2)) = &self.buffer_status {
                             ui.label(format!("Buffer 1: {:?}", buf1));
@@ -464,8 +503,8 @@ impl eframe::App for RoboclawGUI {
                 // ui.group(|ui| {
                 //     ui.vertical(|ui| {
                 //         ui.heading("Config");
-                //         if let Some(config_flags) = &self.config_flags {
-                //             show_config_flags(ui, config_flags);
+                //         if let Some(config) = &self.config {
+                //             show_config(ui, config);
                 //         } else {
                 //             ui.label("Config: ---");
                 //         }
@@ -0,0 +1,197 @@
+/// Controller configuration, as read by
+/// [`Roboclaw::get_config`](crate::Roboclaw::get_config) and written by
+/// [`Roboclaw::set_config`](crate::Roboclaw::set_config).
+///
+/// Several options share bits of the configuration word depending on the
+/// control mode, which is why they live inside [`ControlMode`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Config {
+    pub control_mode: ControlMode,
+    pub battery_mode: BatteryMode,
+    /// Leaves the serial transmit line floating when not answering, so
+    /// several controllers can share it.
+    pub slave_mode: bool,
+    pub relay_mode: bool,
+    pub swap_encoders: bool,
+    pub swap_buttons: bool,
+    pub multi_unit_mode: bool,
+}
+
+/// How the controller is commanded.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ControlMode {
+    /// RC pulses on S1 and S2.
+    Rc(RcOptions),
+    /// Analog voltages on S1 and S2.
+    Analog(RcOptions),
+    SimpleSerial {
+        baud_rate: BaudRate,
+    },
+    /// Packet serial at `address`, from 0x80 to 0x87.
+    PacketSerial {
+        baud_rate: BaudRate,
+        address: u8,
+    },
+}
+
+/// Options of the RC and analog control modes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct RcOptions {
+    /// Treats the inputs as throttle and steering instead of one per motor.
+    pub mixing: bool,
+    pub exponential: bool,
+    /// Lets the speed or position PIDs act on the inputs.
+    pub mcu: bool,
+    pub flip_switch: bool,
+}
+
+/// How the main battery's cell count, and so its voltage limits, are set.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u16)]
+pub enum BatteryMode {
+    Off = 0x0000,
+    /// Detected at power up.
+    Auto = 0x0004,
+    TwoCell = 0x0008,
+    ThreeCell = 0x000C,
+    FourCell = 0x0010,
+    FiveCell = 0x0014,
+    SixCell = 0x0018,
+    SevenCell = 0x001C,
+}
+
+/// Serial baud rate.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u16)]
+pub enum BaudRate {
+    Baud2400 = 0x0000,
+    Baud9600 = 0x0020,
+    Baud19200 = 0x0040,
+    Baud38400 = 0x0060,
+    Baud57600 = 0x0080,
+    Baud115200 = 0x00A0,
+    Baud230400 = 0x00C0,
+    Baud460800 = 0x00E0,
+}
+
+const CONTROL_MODE_MASK: u16 = 0x0003;
+const BATTERY_MODE_MASK: u16 = 0x001C;
+const BAUD_RATE_MASK: u16 = 0x00E0;
+const PACKET_ADDRESS_MASK: u16 = 0x0700;
+
+const MIXING: u16 = 0x0020;
+const EXPONENTIAL: u16 = 0x0040;
+const MCU: u16 = 0x0080;
+const FLIP_SWITCH: u16 = 0x0100;
+const SLAVE_MODE: u16 = 0x0800;
+const RELAY_MODE: u16 = 0x1000;
+const SWAP_ENCODERS: u16 = 0x2000;
+const SWAP_BUTTONS: u16 = 0x4000;
+const MULTI_UNIT_MODE: u16 = 0x8000;
+
+impl Config {
+    /// Decodes a configuration word.
+    pub fn from_bits(bits: u16) -> Self {
+        let flag = |mask| bits & mask != 0;
+        let rc_options = RcOptions {
+            mixing: flag(MIXING),
+            exponential: flag(EXPONENTIAL),
+            mcu: flag(MCU),
+            flip_switch: flag(FLIP_SWITCH),
+        };
+        let baud_rate = BaudRate::from_bits(bits);
+        Config {
+            control_mode: match bits & CONTROL_MODE_MASK {
+                0 => ControlMode::Rc(rc_options),
+                1 => ControlMode::Analog(rc_options),
+                2 => ControlMode::SimpleSerial { baud_rate },
+                _ => ControlMode::PacketSerial {
+                    baud_rate,
+                    address: 0x80 + ((bits & PACKET_ADDRESS_MASK) >> 8) as u8,
+                },
+            },
+            battery_mode: BatteryMode::from_bits(bits),
+            slave_mode: flag(SLAVE_MODE),
+            relay_mode: flag(RELAY_MODE),
+            swap_encoders: flag(SWAP_ENCODERS),
+            swap_buttons: flag(SWAP_BUTTONS),
+            multi_unit_mode: flag(MULTI_UNIT_MODE),
+        }
+    }
+
+    /// Encodes the configuration word, or returns `None` if the packet
+    /// serial address is out of range.
+    pub fn bits(&self) -> Option<u16> {
+        let flag = |set: bool, mask| if set { mask } else { 0 };
+        let rc_bits = |options: &RcOptions| {
+            flag(options.mixing, MIXING)
+                | flag(options.exponential, EXPONENTIAL)
+                | flag(options.mcu, MCU)
+                | flag(options.flip_switch, FLIP_SWITCH)
+        };
+        let mode_bits = match &self.control_mode {
+            ControlMode::Rc(options) => rc_bits(options),
+            ControlMode::Analog(options) => 1 | rc_bits(options),
+            ControlMode::SimpleSerial { baud_rate } => 2 | *baud_rate as u16,
+            ControlMode::PacketSerial { baud_rate, address } => {
+                if !(0x80..=0x87).contains(address) {
+                    return None;
+                }
+                3 | *baud_rate as u16 | ((*address - 0x80) as u16) << 8
+            }
+        };
+        Some(
+            mode_bits
+                | self.battery_mode as u16
+                | flag(self.slave_mode, SLAVE_MODE)
+                | flag(self.relay_mode, RELAY_MODE)
+                | flag(self.swap_encoders, SWAP_ENCODERS)
+                | flag(self.swap_buttons, SWAP_BUTTONS)
+                | flag(self.multi_unit_mode, MULTI_UNIT_MODE),
+        )
+    }
+}
+
+impl BatteryMode {
+    fn from_bits(bits: u16) -> Self {
+        match bits & BATTERY_MODE_MASK {
+            0x0000 => BatteryMode::Off,
+            0x0004 => BatteryMode::Auto,
+            0x0008 => BatteryMode::TwoCell,
+            0x000C => BatteryMode::ThreeCell,
+            0x0010 => BatteryMode::FourCell,
+            0x0014 => BatteryMode::FiveCell,
+            0x0018 => BatteryMode::SixCell,
+            _ => BatteryMode::SevenCell,
+        }
+    }
+}
+
+impl BaudRate {
+    fn from_bits(bits: u16) -> Self {
+        match bits & BAUD_RATE_MASK {
+            0x0000 => BaudRate::Baud2400,
+            0x0020 => BaudRate::Baud9600,
+            0x0040 => BaudRate::Baud19200,
+            0x0060 => BaudRate::Baud38400,
+            0x0080 => BaudRate::Baud57600,
+            0x00A0 => BaudRate::Baud115200,
+            0x00C0 => BaudRate::Baud230400,
+            _ => BaudRate::Baud460800,
+        }
+    }
+
+    /// The rate in bits per second.
+    pub fn bps(self) -> u32 {
+        match self {
+            BaudRate::Baud2400 => 2400,
+            BaudRate::Baud9600 => 9600,
+            BaudRate::Baud19200 => 19200,
+            BaudRate::Baud38400 => 38400,
+            BaudRate::Baud57600 => 57600,
+            BaudRate::Baud115200 => 115200,
+            BaudRate::Baud230400 => 230400,
+            BaudRate::Baud460800 => 460800,
+        }
+    }
+}
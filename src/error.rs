@@ -0,0 +1,84 @@
+use std::fmt;
+use std::io;
+
+/// Everything that can go wrong talking to a controller.
+#[derive(Debug)]
+pub enum RoboclawError {
+    /// The checksum of a response didn't match its contents, usually because
+    /// of line noise or a baud rate mismatch.
+    Crc {
+        command: u8,
+        expected: u16,
+        got: u16,
+    },
+    /// The controller answered a write with something other than the 0xFF
+    /// acknowledgement.
+    Nack { command: u8, byte: u8 },
+    /// The controller didn't answer in time.
+    Timeout,
+    /// The response passed the checksum but couldn't be decoded.
+    InvalidResponse { command: u8, data: Vec<u8> },
+    /// An argument was outside what the controller accepts, so the command
+    /// wasn't sent.
+    InvalidArgument { command: u8, reason: String },
+    /// The connected model doesn't support the command.
+    Unsupported { command: u8 },
+    /// The transport failed.
+    Io(io::Error),
+}
+
+impl fmt::Display for RoboclawError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoboclawError::Crc {
+                command,
+                expected,
+                got,
+            } => write!(
+                f,
+                "crc error: expected {:04X}, got {:04X} (command_code: 0x{:02X})",
+                expected, got, command
+            ),
+            RoboclawError::Nack { command, byte } => write!(
+                f,
+                "return value error: expected 0xFF, got 0x{:02X} (command_code: 0x{:02X})",
+                byte, command
+            ),
+            RoboclawError::Timeout => write!(f, "timed out waiting for a response"),
+            RoboclawError::InvalidResponse { command, data } => write!(
+                f,
+                "invalid response: {:02X?} (command_code: 0x{:02X})",
+                data, command
+            ),
+            RoboclawError::InvalidArgument { command, reason } => write!(
+                f,
+                "invalid argument: {} (command_code: 0x{:02X})",
+                reason, command
+            ),
+            RoboclawError::Unsupported { command } => write!(
+                f,
+                "not supported on this model (command_code: 0x{:02X})",
+                command
+            ),
+            RoboclawError::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RoboclawError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RoboclawError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RoboclawError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::TimedOut => RoboclawError::Timeout,
+            _ => RoboclawError::Io(e),
+        }
+    }
+}
@@ -1,46 +1,24 @@
 use bitflags::bitflags;
+use std::mem;
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
 
-bitflags! {
-    pub struct ConfigFlags: u16 {
-        const RC_MODE = 0x0000;
-        const ANALOG_MODE = 0x0001;
-        const SIMPLE_SERIAL_MODE = 0x0002;
-        const PACKET_SERIAL_MODE = 0x0003;
-        const BATTERY_MODE_OFF = 0x0000;
-        const BATTERY_MODE_AUTO = 0x0004;
-        const BATTERY_MODE_2_CELL = 0x0008;
-        const BATTERY_MODE_3_CELL = 0x000C;
-        const BATTERY_MODE_4_CELL = 0x0010;
-        const BATTERY_MODE_5_CELL = 0x0014;
-        const BATTERY_MODE_6_CELL = 0x0018;
-        const BATTERY_MODE_7_CELL = 0x001C;
-        const MIXING = 0x0020;
-        const EXPONENTIAL = 0x0040;
-        const MCU = 0x0080;
-        const BAUDRATE_2400 = 0x0000;
-        const BAUDRATE_9600 = 0x0020;
-        const BAUDRATE_19200 = 0x0040;
-        const BAUDRATE_38400 = 0x0060;
-        const BAUDRATE_57600 = 0x0080;
-        const BAUDRATE_115200 = 0x00A0;
-        const BAUDRATE_230400 = 0x00C0;
-        const BAUDRATE_460800 = 0x00E0;
-        const FLIPSWITCH = 0x0100;
-        const PACKET_ADDRESS_0X80 = 0x0000;
-        const PACKET_ADDRESS_0X81 = 0x0100;
-        const PACKET_ADDRESS_0X82 = 0x0200;
-        const PACKET_ADDRESS_0X83 = 0x0300;
-        const PACKET_ADDRESS_0X84 = 0x0400;
-        const PACKET_ADDRESS_0X85 = 0x0500;
-        const PACKET_ADDRESS_0X86 = 0x0600;
-        const PACKET_ADDRESS_0X87 = 0x0700;
-        const SLAVE_MODE = 0x0800;
-        const RELAY_MODE = 0x1000;
-        const SWAP_ENCODERS = 0x2000;
-        const SWAP_BUTTONS = 0x4000;
-        const MULTI_UNIT_MODE = 0x8000;
-    }
-}
+mod config;
+mod error;
+mod retry;
+mod simulator;
+mod trajectory;
+mod transport;
+mod version;
+
+pub use config::{BatteryMode, BaudRate, Config, ControlMode, RcOptions};
+pub use error::RoboclawError;
+pub use retry::{RetryPolicy, RetryStats};
+pub use simulator::Simulator;
+pub use trajectory::{Progress, Segment, Trajectory};
+pub use transport::{Loopback, Transport};
+pub use version::{Firmware, Model, Version};
 
 bitflags! {
     pub struct StatusFlags: u32 {
@@ -72,13 +50,51 @@ bitflags! {
     }
 }
 
-const ADDRESS: u8 = 0x80;
+bitflags! {
+    /// How a channel's feedback input is interpreted. Without `ABSOLUTE`
+    /// the input is a quadrature encoder.
+    pub struct EncoderMode: u8 {
+        const ABSOLUTE = 0x01;
+        const REVERSE_MOTOR = 0x20;
+        const REVERSE_ENCODER = 0x40;
+        const RC_ANALOG = 0x80;
+    }
+}
+
+impl EncoderMode {
+    /// Whether the input is an absolute encoder rather than a quadrature one.
+    pub fn is_absolute(&self) -> bool {
+        self.contains(EncoderMode::ABSOLUTE)
+    }
+}
+
+/// Packet serial address a controller uses out of the box.
+pub const DEFAULT_ADDRESS: u8 = 0x80;
+
+/// Key that must accompany a WriteNVM command.
+const NVM_KEY: u32 = 0xE22E_AB7A;
+
+/// Main battery limits accepted by [`Roboclaw::set_main_voltages`].
+pub const MAIN_VOLTAGE_RANGE: RangeInclusive<f32> = 6.0..=34.0;
+
+/// Main battery limits accepted by [`Roboclaw::set_main_voltages`] on HV
+/// boards.
+pub const HV_MAIN_VOLTAGE_RANGE: RangeInclusive<f32> = 6.0..=60.0;
+
+/// Logic battery limits accepted by [`Roboclaw::set_logic_voltages`].
+pub const LOGIC_VOLTAGE_RANGE: RangeInclusive<f32> = 6.0..=34.0;
+
+/// Lower logic battery limits accepted by [`Roboclaw::set_logic_voltages`]
+/// and [`Roboclaw::set_min_voltage_logic_battery`], which stop short of
+/// [`LOGIC_VOLTAGE_RANGE`].
+pub const LOGIC_MIN_VOLTAGE_RANGE: RangeInclusive<f32> = 6.0..=28.0;
 
 // There's a bunch of code here for stuff that's not implemented yet, but could
 // be. Keeping it makes it easier for developers in the future to add these
 // extensions, so we'll just ignore dead code for now.
 #[allow(dead_code)]
 #[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 #[repr(u8)]
 enum Command {
     M1FORWARD = 0,
@@ -175,6 +191,342 @@ enum Command {
     FLAGBOOTLOADER = 255, //Only available via USB communications
 }
 
+impl TryFrom<u8> for Command {
+    type Error = u8;
+
+    fn try_from(code: u8) -> Result<Self, Self::Error> {
+        Ok(match code {
+            0 => Command::M1FORWARD,
+            1 => Command::M1BACKWARD,
+            2 => Command::SETMINMB,
+            3 => Command::SETMAXMB,
+            4 => Command::M2FORWARD,
+            5 => Command::M2BACKWARD,
+            6 => Command::M17BIT,
+            7 => Command::M27BIT,
+            8 => Command::MIXEDFORWARD,
+            9 => Command::MIXEDBACKWARD,
+            10 => Command::MIXEDRIGHT,
+            11 => Command::MIXEDLEFT,
+            12 => Command::MIXEDFB,
+            13 => Command::MIXEDLR,
+            16 => Command::GETM1ENC,
+            17 => Command::GETM2ENC,
+            18 => Command::GETM1SPEED,
+            19 => Command::GETM2SPEED,
+            20 => Command::RESETENC,
+            21 => Command::GETVERSION,
+            22 => Command::SETM1ENCCOUNT,
+            23 => Command::SETM2ENCCOUNT,
+            24 => Command::GETMBATT,
+            25 => Command::GETLBATT,
+            26 => Command::SETMINLB,
+            27 => Command::SETMAXLB,
+            28 => Command::SETM1PID,
+            29 => Command::SETM2PID,
+            30 => Command::GETM1ISPEED,
+            31 => Command::GETM2ISPEED,
+            32 => Command::M1DUTY,
+            33 => Command::M2DUTY,
+            34 => Command::MIXEDDUTY,
+            35 => Command::M1SPEED,
+            36 => Command::M2SPEED,
+            37 => Command::MIXEDSPEED,
+            38 => Command::M1SPEEDACCEL,
+            39 => Command::M2SPEEDACCEL,
+            40 => Command::MIXEDSPEEDACCEL,
+            41 => Command::M1SPEEDDIST,
+            42 => Command::M2SPEEDDIST,
+            43 => Command::MIXEDSPEEDDIST,
+            44 => Command::M1SPEEDACCELDIST,
+            45 => Command::M2SPEEDACCELDIST,
+            46 => Command::MIXEDSPEEDACCELDIST,
+            47 => Command::GETBUFFERS,
+            48 => Command::GETPWMS,
+            49 => Command::GETCURRENTS,
+            50 => Command::MIXEDSPEED2ACCEL,
+            51 => Command::MIXEDSPEED2ACCELDIST,
+            52 => Command::M1DUTYACCEL,
+            53 => Command::M2DUTYACCEL,
+            54 => Command::MIXEDDUTYACCEL,
+            55 => Command::READM1PID,
+            56 => Command::READM2PID,
+            57 => Command::SETMAINVOLTAGES,
+            58 => Command::SETLOGICVOLTAGES,
+            59 => Command::GETMINMAXMAINVOLTAGES,
+            60 => Command::GETMINMAXLOGICVOLTAGES,
+            61 => Command::SETM1POSPID,
+            62 => Command::SETM2POSPID,
+            63 => Command::READM1POSPID,
+            64 => Command::READM2POSPID,
+            65 => Command::M1SPEEDACCELDECCELPOS,
+            66 => Command::M2SPEEDACCELDECCELPOS,
+            67 => Command::MIXEDSPEEDACCELDECCELPOS,
+            68 => Command::SETM1DEFAULTACCEL,
+            69 => Command::SETM2DEFAULTACCEL,
+            74 => Command::SETPINFUNCTIONS,
+            75 => Command::GETPINFUNCTIONS,
+            76 => Command::SETDEADBAND,
+            77 => Command::GETDEADBAND,
+            78 => Command::GETENCODERS,
+            79 => Command::GETISPEEDS,
+            80 => Command::RESTOREDEFAULTS,
+            82 => Command::GETTEMP,
+            83 => Command::GETTEMP2,
+            90 => Command::GETERROR,
+            91 => Command::GETENCODERMODE,
+            92 => Command::SETM1ENCODERMODE,
+            93 => Command::SETM2ENCODERMODE,
+            94 => Command::WRITENVM,
+            95 => Command::READNVM,
+            98 => Command::SETCONFIG,
+            99 => Command::GETCONFIG,
+            133 => Command::SETM1MAXCURRENT,
+            134 => Command::SETM2MAXCURRENT,
+            135 => Command::GETM1MAXCURRENT,
+            136 => Command::GETM2MAXCURRENT,
+            148 => Command::SETPWMMODE,
+            149 => Command::GETPWMMODE,
+            255 => Command::FLAGBOOTLOADER,
+            _ => return Err(code),
+        })
+    }
+}
+
+/// One of the two motor channels.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Motor {
+    M1,
+    M2,
+}
+
+impl Motor {
+    /// Both channels, for looping over them.
+    pub const ALL: [Motor; 2] = [Motor::M1, Motor::M2];
+
+    /// Picks the code of whichever of a pair of commands applies to this
+    /// channel.
+    fn command(self, m1: Command, m2: Command) -> u8 {
+        match self {
+            Motor::M1 => m1 as u8,
+            Motor::M2 => m2 as u8,
+        }
+    }
+}
+
+/// Velocity PID settings of one channel.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct VelocityPid {
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+    /// Speed of the motor at full duty, in encoder counts per second.
+    pub qpps: u32,
+}
+
+/// Position PID settings of one channel.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PositionPid {
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+    /// Limit of the integral term.
+    pub ki_max: u32,
+    /// Position error, in encoder counts, below which the motor isn't driven.
+    pub deadzone: u32,
+    /// Lowest position the controller will move to.
+    pub min: i32,
+    /// Highest position the controller will move to.
+    pub max: i32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+/// A speed reading.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Speed {
+    /// Encoder counts per second, negative when running backwards.
+    pub qpps: i32,
+    /// Direction as reported by the status byte.
+    pub direction: Direction,
+}
+
+impl Speed {
+    /// Decodes a 4 byte speed followed by a status byte whose lowest bit is
+    /// set when running backwards.
+    fn from_bytes(data: &[u8]) -> Self {
+        let value = join_u8_u32(data[0], data[1], data[2], data[3]) as i32;
+        let direction = if data[4] & 0x01 != 0 {
+            Direction::Backward
+        } else {
+            Direction::Forward
+        };
+        // Some firmware versions send the magnitude and leave the sign to
+        // the status byte.
+        let qpps = if direction == Direction::Backward && value > 0 {
+            -value
+        } else {
+            value
+        };
+        Speed { qpps, direction }
+    }
+}
+
+/// An encoder reading.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Encoder {
+    pub count: i32,
+    /// The count wrapped from 0 to the maximum since the last read.
+    pub underflow: bool,
+    /// The count wrapped from the maximum to 0 since the last read.
+    pub overflow: bool,
+    pub direction: Direction,
+}
+
+impl Encoder {
+    /// Decodes a 4 byte count followed by a status byte: bit 0 underflow,
+    /// bit 1 backward, bit 2 overflow.
+    fn from_bytes(data: &[u8]) -> Self {
+        Encoder {
+            count: join_u8_u32(data[0], data[1], data[2], data[3]) as i32,
+            underflow: data[4] & 0x01 != 0,
+            overflow: data[4] & 0x04 != 0,
+            direction: if data[4] & 0x02 != 0 {
+                Direction::Backward
+            } else {
+                Direction::Forward
+            },
+        }
+    }
+}
+
+/// What the S3 input does.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum S3Mode {
+    Default = 0,
+    /// Stops the motors until the controller is reset.
+    EStopLatching = 1,
+    /// Stops the motors while the input is active.
+    EStop = 2,
+    /// Drives the output that switches a voltage clamp resistor.
+    VoltageClamp = 3,
+}
+
+impl TryFrom<u8> for S3Mode {
+    type Error = u8;
+
+    fn try_from(mode: u8) -> Result<Self, Self::Error> {
+        Ok(match mode {
+            0 => S3Mode::Default,
+            1 => S3Mode::EStopLatching,
+            2 => S3Mode::EStop,
+            3 => S3Mode::VoltageClamp,
+            _ => return Err(mode),
+        })
+    }
+}
+
+/// What the S4 or S5 input does. Home and limit switches act on M1 when
+/// wired to S4 and on M2 when wired to S5.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum SwitchMode {
+    Disabled = 0,
+    /// Stops the motors until the controller is reset.
+    EStopLatching = 1,
+    /// Stops the motors while the input is active.
+    EStop = 2,
+    /// Drives the output that switches a voltage clamp resistor.
+    VoltageClamp = 3,
+    /// Zeroes the encoder when the motor reaches the switch.
+    Home = 4,
+    /// Stops the motor from moving forwards while active.
+    LimitForward = 5,
+    /// Stops the motor from moving backwards while active.
+    LimitReverse = 6,
+    /// Stops the motor in either direction while active.
+    LimitBoth = 7,
+}
+
+impl TryFrom<u8> for SwitchMode {
+    type Error = u8;
+
+    fn try_from(mode: u8) -> Result<Self, Self::Error> {
+        Ok(match mode {
+            0 => SwitchMode::Disabled,
+            1 => SwitchMode::EStopLatching,
+            2 => SwitchMode::EStop,
+            3 => SwitchMode::VoltageClamp,
+            4 => SwitchMode::Home,
+            5 => SwitchMode::LimitForward,
+            6 => SwitchMode::LimitReverse,
+            7 => SwitchMode::LimitBoth,
+            _ => return Err(mode),
+        })
+    }
+}
+
+/// Functions of the S3, S4 and S5 inputs.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PinFunctions {
+    pub s3: S3Mode,
+    pub s4: SwitchMode,
+    pub s5: SwitchMode,
+}
+
+/// How the motor outputs are switched.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum PwmMode {
+    LockedAntiphase = 0,
+    SignMagnitude = 1,
+}
+
+impl TryFrom<u8> for PwmMode {
+    type Error = u8;
+
+    fn try_from(mode: u8) -> Result<Self, Self::Error> {
+        Ok(match mode {
+            0 => PwmMode::LockedAntiphase,
+            1 => PwmMode::SignMagnitude,
+            _ => return Err(mode),
+        })
+    }
+}
+
+/// Proof that the caller means to overwrite the controller's settings,
+/// required by [`Roboclaw::write_nvm`] and [`Roboclaw::restore_defaults`].
+#[derive(Debug)]
+pub struct Confirm(());
+
+impl Confirm {
+    /// Confirms that settings stored on the controller may be replaced.
+    pub fn overwrite_settings() -> Self {
+        Confirm(())
+    }
+}
+
+/// Whether a buffered motion command waits for the ones before it.
+///
+/// Each channel has a buffer of motion commands that run one after the
+/// other. The default, [`Buffer::Immediate`], is what the commands used
+/// before they took this parameter.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[repr(u8)]
+pub enum Buffer {
+    /// Runs the command once the ones already buffered are done. Sent
+    /// without retries, so a lost acknowledgement can't queue it twice.
+    Queue = 0,
+    /// Discards the buffered commands and runs this one right away.
+    #[default]
+    Immediate = 1,
+}
+
 #[derive(PartialEq, Debug)]
 pub enum BufferStatus {
     NotEmpty(u8),
@@ -206,221 +558,619 @@ fn join_u8_u32(byte0: u8, byte1: u8, byte2: u8, byte3: u8) -> u32 {
     ((byte0 as u32) << 24) | ((byte1 as u32) << 16) | ((byte2 as u32) << 8) | (byte3 as u32)
 }
 
+/// Converts a gain to the controller's 16.16 fixed-point format.
+fn to_fixed_16_16(x: f32) -> u32 {
+    (x as f64 * 65536.0).round() as u32
+}
+
+fn from_fixed_16_16(x: u32) -> f32 {
+    (x as f64 / 65536.0) as f32
+}
+
+/// Converts a position PID gain to the controller's 22.10 fixed-point
+/// format.
+fn to_fixed_22_10(x: f32) -> u32 {
+    (x as f64 * 1024.0).round() as u32
+}
+
+fn from_fixed_22_10(x: u32) -> f32 {
+    (x as f64 / 1024.0) as f32
+}
+
 fn crc(buf: &[u8]) -> Vec<u8> {
     let crc = crc16::State::<crc16::XMODEM>::calculate(buf);
     split_u16_u8(crc).to_vec()
 }
 
-pub struct Roboclaw {
-    port: Box<dyn serialport::SerialPort>,
+/// Checks the CRC a controller sent after its response to `command_code`.
+/// `packet` is everything the CRC covers: the request and the response data.
+fn check_crc(command_code: u8, packet: &[u8], crc: [u8; 2]) -> Result<(), RoboclawError> {
+    let crc_read = join_u8(crc[0], crc[1]);
+    let crc_calc = crc16::State::<crc16::XMODEM>::calculate(packet);
+    if crc_read == crc_calc {
+        Ok(())
+    } else {
+        Err(RoboclawError::Crc {
+            command: command_code,
+            expected: crc_calc,
+            got: crc_read,
+        })
+    }
+}
+
+/// Checks that `volts` is within what `command_code` accepts.
+fn check_voltage(
+    command_code: u8,
+    volts: f32,
+    range: RangeInclusive<f32>,
+) -> Result<(), RoboclawError> {
+    if range.contains(&volts) {
+        Ok(())
+    } else {
+        Err(RoboclawError::InvalidArgument {
+            command: command_code,
+            reason: format!(
+                "{}V is outside {}V to {}V",
+                volts,
+                range.start(),
+                range.end()
+            ),
+        })
+    }
+}
+
+/// Checks a pair of voltage limits and converts them to tenths of a volt.
+fn voltage_limits(
+    command_code: u8,
+    min: f32,
+    max: f32,
+    range: RangeInclusive<f32>,
+) -> Result<Vec<u8>, RoboclawError> {
+    check_voltage(command_code, min, range.clone())?;
+    check_voltage(command_code, max, range)?;
+    if min > max {
+        return Err(RoboclawError::InvalidArgument {
+            command: command_code,
+            reason: format!("minimum {}V is above maximum {}V", min, max),
+        });
+    }
+    let min_bytes = split_u16_u8((min * 10.0).round() as u16);
+    let max_bytes = split_u16_u8((max * 10.0).round() as u16);
+    Ok([&min_bytes[..], &max_bytes[..]].concat())
+}
+
+/// Sends a write packet and checks for the 0xFF acknowledgement.
+fn write_packet<T: Transport>(
+    port: &mut T,
+    command_code: u8,
+    packet: &[u8],
+) -> Result<(), RoboclawError> {
+    port.write_all(packet)?;
+    let mut buf = [0; 1];
+    port.read_exact(&mut buf)?;
+    if buf[0] == 0xFF {
+        Ok(())
+    } else {
+        Err(RoboclawError::Nack {
+            command: command_code,
+            byte: buf[0],
+        })
+    }
+}
+
+/// A handle to one controller on a packet serial bus.
+///
+/// Several controllers can share a single UART when they are configured with
+/// distinct packet addresses (0x80 to 0x87) and multi-unit mode. Use
+/// [`Roboclaw::at_address`] to get a handle for each of them; all handles
+/// share the same port and every attempt at a packet exchange holds it
+/// exclusively, so handles can be used from different threads without
+/// interleaving traffic.
+///
+/// The port can be anything implementing [`Transport`]; by default it's a
+/// serial port opened with the `serialport` crate.
+pub struct Roboclaw<T = Box<dyn serialport::SerialPort>> {
+    port: Arc<Mutex<T>>,
+    address: u8,
+    retry_policy: RetryPolicy,
+    retry_stats: RetryStats,
+    /// Last version read, so that model checks don't cost a packet each.
+    version: Option<Version>,
 }
 
-impl Roboclaw {
-    pub fn new(port: Box<dyn serialport::SerialPort>) -> Self {
-        Roboclaw { port }
+impl<T: Transport> Roboclaw<T> {
+    /// Creates a handle for the controller at [`DEFAULT_ADDRESS`].
+    pub fn new(port: T) -> Self {
+        Self::with_address(port, DEFAULT_ADDRESS)
+    }
+
+    /// Creates a handle for the controller at `address`.
+    pub fn with_address(port: T, address: u8) -> Self {
+        Roboclaw {
+            port: Arc::new(Mutex::new(port)),
+            address,
+            retry_policy: RetryPolicy::default(),
+            retry_stats: RetryStats::default(),
+            version: None,
+        }
+    }
+
+    /// Returns a handle for the controller at `address` on the same port.
+    ///
+    /// The new handle uses the same retry policy and starts with fresh
+    /// statistics.
+    pub fn at_address(&self, address: u8) -> Self {
+        Roboclaw {
+            port: Arc::clone(&self.port),
+            address,
+            retry_policy: self.retry_policy.clone(),
+            retry_stats: RetryStats::default(),
+            version: None,
+        }
+    }
+
+    /// Uses `policy` instead of [`RetryPolicy::default`].
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = policy;
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    /// Link quality counters since the handle was created or last reset.
+    pub fn retry_stats(&self) -> RetryStats {
+        self.retry_stats
+    }
+
+    pub fn reset_retry_stats(&mut self) {
+        self.retry_stats = RetryStats::default();
     }
 
-    fn read_command(&mut self, command_code: u8, num_bytes: usize) -> std::io::Result<Vec<u8>> {
+    /// Packet serial address this handle sends commands to.
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Runs `f` with retries turned off, for writes that mustn't be sent
+    /// twice.
+    fn without_retries<R>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<R, RoboclawError>,
+    ) -> Result<R, RoboclawError> {
+        let single = RetryPolicy {
+            attempts: 1,
+            ..self.retry_policy.clone()
+        };
+        let policy = mem::replace(&mut self.retry_policy, single);
+        let result = f(self);
+        self.retry_policy = policy;
+        result
+    }
+
+    /// Runs one packet exchange, retrying it according to the retry policy.
+    /// The port is locked for each attempt but not while backing off, so
+    /// other handles on the same bus can get a packet in.
+    fn exchange<R>(
+        &mut self,
+        mut attempt: impl FnMut(&mut T) -> Result<R, RoboclawError>,
+    ) -> Result<R, RoboclawError> {
+        let policy = &self.retry_policy;
+        let stats = &mut self.retry_stats;
+        stats.packets += 1;
+        let mut backoff = policy.backoff;
+        let mut attempts_left = policy.attempts.max(1);
+        loop {
+            attempts_left -= 1;
+            let result = {
+                let mut port = self.port.lock().unwrap_or_else(PoisonError::into_inner);
+                if policy.flush {
+                    port.clear()?;
+                }
+                attempt(&mut port)
+            };
+            let error = match result {
+                Ok(result) => return Ok(result),
+                Err(error) => error,
+            };
+            if !stats.record(&error) || attempts_left == 0 {
+                stats.failures += 1;
+                return Err(error);
+            }
+            stats.retries += 1;
+            if !backoff.is_zero() {
+                thread::sleep(backoff);
+                backoff *= 2;
+            }
+        }
+    }
+
+    fn read_command(
+        &mut self,
+        command_code: u8,
+        num_bytes: usize,
+    ) -> Result<Vec<u8>, RoboclawError> {
         const CRC_SIZE: usize = 2;
-        let command = vec![ADDRESS, command_code];
-        self.port.clear(serialport::ClearBuffer::All)?;
-        self.port.write_all(&command[..])?;
-        let mut buf = vec![0; num_bytes + CRC_SIZE];
-        self.port.read_exact(&mut buf)?;
-        let crc = buf.split_off(num_bytes);
-        let crc_read = join_u8(crc[0], crc[1]);
-        let crc_calc = crc16::State::<crc16::XMODEM>::calculate(&[&command[..], &buf].concat());
-        if crc_read == crc_calc {
+        let command = [self.address, command_code];
+        self.exchange(|port| {
+            port.write_all(&command[..])?;
+            let mut buf = vec![0; num_bytes + CRC_SIZE];
+            port.read_exact(&mut buf)?;
+            let crc = buf.split_off(num_bytes);
+            check_crc(
+                command_code,
+                &[&command[..], &buf].concat(),
+                [crc[0], crc[1]],
+            )?;
             Ok(buf)
-        } else {
-            Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!(
-                    "crc error: expected {:04X}, got {:04X} (command_code: 0x{:02X}, data: {:02X?})",
-                    crc_calc, crc_read, command_code, buf
-                ),
-            ))
+        })
+    }
+
+    /// Like `read_command`, for responses that are a NUL-terminated string
+    /// rather than a fixed number of bytes.
+    fn read_string_command(&mut self, command_code: u8) -> Result<String, RoboclawError> {
+        // The longest string the firmware sends, terminator included.
+        const MAX_LEN: usize = 48;
+        let command = [self.address, command_code];
+        self.exchange(|port| {
+            port.write_all(&command[..])?;
+            let mut buf = Vec::new();
+            let mut byte = [0];
+            while buf.last() != Some(&0) {
+                if buf.len() == MAX_LEN {
+                    return Err(RoboclawError::InvalidResponse {
+                        command: command_code,
+                        data: buf,
+                    });
+                }
+                port.read_exact(&mut byte)?;
+                buf.push(byte[0]);
+            }
+            let mut crc = [0; 2];
+            port.read_exact(&mut crc)?;
+            check_crc(command_code, &[&command[..], &buf].concat(), crc)?;
+            buf.pop();
+            String::from_utf8(buf).map_err(|e| RoboclawError::InvalidResponse {
+                command: command_code,
+                data: e.into_bytes(),
+            })
+        })
+    }
+
+    /// The board model, read from the controller the first time it's needed.
+    fn model(&mut self) -> Result<Option<Model>, RoboclawError> {
+        match &self.version {
+            Some(version) => Ok(version.model),
+            None => self.read_version().map(|version| version.model),
         }
     }
 
-    fn write_simple_command(&mut self, command_code: u8) -> std::io::Result<()> {
-        let command = vec![ADDRESS, command_code];
+    fn write_simple_command(&mut self, command_code: u8) -> Result<(), RoboclawError> {
+        let command = vec![self.address, command_code];
         let crc = crc(&command);
-        let command_bytes = [&[ADDRESS], &command[..], &crc[..]].concat();
-        self.port.write_all(&command_bytes)?;
-        let mut buf = vec![0; 1];
-        self.port.read_exact(&mut buf)?;
-        if buf[0] == 0xFF {
-            Ok(())
-        } else {
-            Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!(
-                    "return value error: expected 0xFF, got 0x{:02X} (command_code: 0x{:02X})",
-                    buf[0], command_code
-                ),
-            ))
-        }
+        let command_bytes = [&command[..], &crc[..]].concat();
+        self.exchange(|port| write_packet(port, command_code, &command_bytes))
     }
 
-    fn write_command(&mut self, command_code: u8, mut data: Vec<u8>) -> std::io::Result<()> {
-        let mut command = vec![ADDRESS, command_code];
+    fn write_command(&mut self, command_code: u8, mut data: Vec<u8>) -> Result<(), RoboclawError> {
+        let mut command = vec![self.address, command_code];
         command.append(&mut data);
         let crc = crc(&command);
-        let command_bytes = [&[ADDRESS], &command[..], &crc[..]].concat();
-        self.port.write_all(&command_bytes)?;
-        let mut buf = vec![0; 1];
-        self.port.read_exact(&mut buf)?;
-        if buf[0] == 0xFF {
-            Ok(())
-        } else {
-            Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!(
-                    "return value error: expected 0xFF, got 0x{:02X} (command_code: 0x{:02X}, data: {:02X?})",
-                    buf[0], command_code, data
-                ),
-            ))
+        let command_bytes = [&command[..], &crc[..]].concat();
+        self.exchange(|port| write_packet(port, command_code, &command_bytes))
+    }
+
+    /// Like `write_command`, for motion commands taking a [`Buffer`] flag.
+    /// Queued ones are sent only once, since resending one whose
+    /// acknowledgement got lost would queue it twice.
+    fn write_buffered_command(
+        &mut self,
+        command_code: u8,
+        mut data: Vec<u8>,
+        buffer: Buffer,
+    ) -> Result<(), RoboclawError> {
+        data.push(buffer as u8);
+        match buffer {
+            Buffer::Queue => {
+                self.without_retries(|roboclaw| roboclaw.write_command(command_code, data))
+            }
+            Buffer::Immediate => self.write_command(command_code, data),
         }
     }
 
-    pub fn forward_m1(&mut self, speed: u8) -> std::io::Result<()> {
-        self.write_command(Command::M1FORWARD as u8, vec![speed])
+    pub fn forward(&mut self, motor: Motor, speed: u8) -> Result<(), RoboclawError> {
+        self.write_command(
+            motor.command(Command::M1FORWARD, Command::M2FORWARD),
+            vec![speed],
+        )
+    }
+
+    pub fn backward(&mut self, motor: Motor, speed: u8) -> Result<(), RoboclawError> {
+        self.write_command(
+            motor.command(Command::M1BACKWARD, Command::M2BACKWARD),
+            vec![speed],
+        )
+    }
+
+    pub fn forward_backward(&mut self, motor: Motor, speed: u8) -> Result<(), RoboclawError> {
+        self.write_command(motor.command(Command::M17BIT, Command::M27BIT), vec![speed])
+    }
+
+    pub fn forward_m1(&mut self, speed: u8) -> Result<(), RoboclawError> {
+        self.forward(Motor::M1, speed)
     }
 
-    pub fn backward_m1(&mut self, speed: u8) -> std::io::Result<()> {
-        self.write_command(Command::M1BACKWARD as u8, vec![speed])
+    pub fn backward_m1(&mut self, speed: u8) -> Result<(), RoboclawError> {
+        self.backward(Motor::M1, speed)
     }
 
-    pub fn forward_m2(&mut self, speed: u8) -> std::io::Result<()> {
-        self.write_command(Command::M2FORWARD as u8, vec![speed])
+    pub fn forward_m2(&mut self, speed: u8) -> Result<(), RoboclawError> {
+        self.forward(Motor::M2, speed)
     }
 
-    pub fn backward_m2(&mut self, speed: u8) -> std::io::Result<()> {
-        self.write_command(Command::M2BACKWARD as u8, vec![speed])
+    pub fn backward_m2(&mut self, speed: u8) -> Result<(), RoboclawError> {
+        self.backward(Motor::M2, speed)
     }
 
-    pub fn forward_backward_m1(&mut self, speed: u8) -> std::io::Result<()> {
-        self.write_command(Command::M17BIT as u8, vec![speed])
+    pub fn forward_backward_m1(&mut self, speed: u8) -> Result<(), RoboclawError> {
+        self.forward_backward(Motor::M1, speed)
     }
 
-    pub fn forward_backward_m2(&mut self, speed: u8) -> std::io::Result<()> {
-        self.write_command(Command::M27BIT as u8, vec![speed])
+    pub fn forward_backward_m2(&mut self, speed: u8) -> Result<(), RoboclawError> {
+        self.forward_backward(Motor::M2, speed)
     }
 
-    pub fn forward_mixed(&mut self, speed: u8) -> Result<(), std::io::Error> {
+    pub fn forward_mixed(&mut self, speed: u8) -> Result<(), RoboclawError> {
         self.write_command(Command::MIXEDFORWARD as u8, vec![speed])
     }
 
-    pub fn backward_mixed(&mut self, speed: u8) -> std::io::Result<()> {
+    pub fn backward_mixed(&mut self, speed: u8) -> Result<(), RoboclawError> {
         self.write_command(Command::MIXEDBACKWARD as u8, vec![speed])
     }
 
-    pub fn turn_right_mixed(&mut self, speed: u8) -> std::io::Result<()> {
+    pub fn turn_right_mixed(&mut self, speed: u8) -> Result<(), RoboclawError> {
         self.write_command(Command::MIXEDRIGHT as u8, vec![speed])
     }
 
-    pub fn turn_left_mixed(&mut self, speed: u8) -> std::io::Result<()> {
+    pub fn turn_left_mixed(&mut self, speed: u8) -> Result<(), RoboclawError> {
         self.write_command(Command::MIXEDLEFT as u8, vec![speed])
     }
 
-    pub fn forward_backward_mixed(&mut self, speed: u8) -> std::io::Result<()> {
+    pub fn forward_backward_mixed(&mut self, speed: u8) -> Result<(), RoboclawError> {
         self.write_command(Command::MIXEDFB as u8, vec![speed])
     }
 
-    pub fn left_right_mixed(&mut self, speed: u8) -> std::io::Result<()> {
+    pub fn left_right_mixed(&mut self, speed: u8) -> Result<(), RoboclawError> {
         self.write_command(Command::MIXEDLR as u8, vec![speed])
     }
 
-    /*
-    uint32_t ReadEncM1(uint8_t address, uint8_t *status=NULL,bool *valid=NULL);
-    uint32_t ReadEncM2(uint8_t address, uint8_t *status=NULL,bool *valid=NULL);
-    bool SetEncM1(uint8_t address, int32_t val);
-    bool SetEncM2(uint8_t address, int32_t val);
-    uint32_t ReadSpeedM1(uint8_t address, uint8_t *status=NULL,bool *valid=NULL);
-    uint32_t ReadSpeedM2(uint8_t address, uint8_t *status=NULL,bool *valid=NULL);
-    */
+    //uint32_t ReadEncM1(uint8_t address, uint8_t *status=NULL,bool *valid=NULL);
+    //uint32_t ReadEncM2(uint8_t address, uint8_t *status=NULL,bool *valid=NULL);
+    pub fn read_encoder(&mut self, motor: Motor) -> Result<Encoder, RoboclawError> {
+        self.read_command(motor.command(Command::GETM1ENC, Command::GETM2ENC), 5)
+            .map(|data| Encoder::from_bytes(&data))
+    }
+
+    //bool SetEncM1(uint8_t address, int32_t val);
+    //bool SetEncM2(uint8_t address, int32_t val);
+    /// Presets the encoder count of `motor`, leaving the other channel alone.
+    pub fn set_encoder(&mut self, motor: Motor, value: i32) -> Result<(), RoboclawError> {
+        self.write_command(
+            motor.command(Command::SETM1ENCCOUNT, Command::SETM2ENCCOUNT),
+            split_i32_u8(value).to_vec(),
+        )
+    }
+
+    //uint32_t ReadSpeedM1(uint8_t address, uint8_t *status=NULL,bool *valid=NULL);
+    //uint32_t ReadSpeedM2(uint8_t address, uint8_t *status=NULL,bool *valid=NULL);
+    /// Filtered speed of `motor`, as used by the velocity PID.
+    pub fn read_speed(&mut self, motor: Motor) -> Result<Speed, RoboclawError> {
+        self.read_command(motor.command(Command::GETM1SPEED, Command::GETM2SPEED), 5)
+            .map(|data| Speed::from_bytes(&data))
+    }
 
     //bool ResetEncoders(uint8_t address);
-    pub fn reset_encoders(&mut self) -> Result<(), std::io::Error> {
+    pub fn reset_encoders(&mut self) -> Result<(), RoboclawError> {
         self.write_simple_command(Command::RESETENC as u8)
     }
 
-    /*
-    bool ReadVersion(uint8_t address,char *version);
-    */
+    //bool ReadVersion(uint8_t address,char *version);
+    pub fn read_version(&mut self) -> Result<Version, RoboclawError> {
+        let text = self.read_string_command(Command::GETVERSION as u8)?;
+        let version = Version::parse(&text);
+        self.version = Some(version.clone());
+        Ok(version)
+    }
 
     //uint16_t ReadMainBatteryVoltage(uint8_t address,bool *valid=NULL);
-    pub fn read_main_battery_voltage(&mut self) -> Result<f32, std::io::Error> {
+    pub fn read_main_battery_voltage(&mut self) -> Result<f32, RoboclawError> {
         self.read_command(Command::GETMBATT as u8, 2)
             .map(|data| (join_u8(data[0], data[1]) as f32) / 10.0)
     }
 
     //uint16_t ReadLogicBatteryVoltage(uint8_t address,bool *valid=NULL);
-    pub fn read_logic_battery_voltage(&mut self) -> Result<f32, std::io::Error> {
+    pub fn read_logic_battery_voltage(&mut self) -> Result<f32, RoboclawError> {
         self.read_command(Command::GETLBATT as u8, 2)
             .map(|data| (join_u8(data[0], data[1]) as f32) / 10.0)
     }
 
-    /*
-    bool SetMinVoltageMainBattery(uint8_t address, uint8_t voltage);
-    bool SetMaxVoltageMainBattery(uint8_t address, uint8_t voltage);
-    bool SetMinVoltageLogicBattery(uint8_t address, uint8_t voltage);
-    bool SetMaxVoltageLogicBattery(uint8_t address, uint8_t voltage);
-    bool SetM1VelocityPID(uint8_t address, float Kp, float Ki, float Kd, uint32_t qpps);
-    bool SetM2VelocityPID(uint8_t address, float Kp, float Ki, float Kd, uint32_t qpps);
-    uint32_t ReadISpeedM1(uint8_t address,uint8_t *status=NULL,bool *valid=NULL);
-    uint32_t ReadISpeedM2(uint8_t address,uint8_t *status=NULL,bool *valid=NULL);
-    */
+    //bool SetMinVoltageMainBattery(uint8_t address, uint8_t voltage);
+    /// Legacy form of the lower limit in [`set_main_voltages`](Self::set_main_voltages),
+    /// within [`MAIN_VOLTAGE_RANGE`] in steps of 0.2V.
+    pub fn set_min_voltage_main_battery(&mut self, volts: f32) -> Result<(), RoboclawError> {
+        check_voltage(Command::SETMINMB as u8, volts, MAIN_VOLTAGE_RANGE)?;
+        self.write_command(
+            Command::SETMINMB as u8,
+            vec![((volts - 6.0) * 5.0).round() as u8],
+        )
+    }
 
-    //bool DutyM1(uint8_t address, uint16_t duty);
-    pub fn duty_m1(&mut self, duty: i16) -> std::io::Result<()> {
-        self.write_command(Command::M1DUTY as u8, split_i16_u8(duty).to_vec())
+    //bool SetMaxVoltageMainBattery(uint8_t address, uint8_t voltage);
+    /// Legacy form of the upper limit in [`set_main_voltages`](Self::set_main_voltages),
+    /// within [`MAIN_VOLTAGE_RANGE`] in steps of about 0.2V.
+    pub fn set_max_voltage_main_battery(&mut self, volts: f32) -> Result<(), RoboclawError> {
+        check_voltage(Command::SETMAXMB as u8, volts, MAIN_VOLTAGE_RANGE)?;
+        self.write_command(Command::SETMAXMB as u8, vec![(volts * 5.12).round() as u8])
+    }
+
+    //bool SetMinVoltageLogicBattery(uint8_t address, uint8_t voltage);
+    /// Legacy form of the lower limit in [`set_logic_voltages`](Self::set_logic_voltages),
+    /// within [`LOGIC_MIN_VOLTAGE_RANGE`] in steps of 0.2V.
+    pub fn set_min_voltage_logic_battery(&mut self, volts: f32) -> Result<(), RoboclawError> {
+        check_voltage(Command::SETMINLB as u8, volts, LOGIC_MIN_VOLTAGE_RANGE)?;
+        self.write_command(
+            Command::SETMINLB as u8,
+            vec![((volts - 6.0) * 5.0).round() as u8],
+        )
     }
 
+    //bool SetMaxVoltageLogicBattery(uint8_t address, uint8_t voltage);
+    /// Legacy form of the upper limit in [`set_logic_voltages`](Self::set_logic_voltages),
+    /// within [`LOGIC_VOLTAGE_RANGE`] in steps of about 0.2V.
+    pub fn set_max_voltage_logic_battery(&mut self, volts: f32) -> Result<(), RoboclawError> {
+        check_voltage(Command::SETMAXLB as u8, volts, LOGIC_VOLTAGE_RANGE)?;
+        self.write_command(Command::SETMAXLB as u8, vec![(volts * 5.12).round() as u8])
+    }
+
+    //bool SetM1VelocityPID(uint8_t address, float Kp, float Ki, float Kd, uint32_t qpps);
+    //bool SetM2VelocityPID(uint8_t address, float Kp, float Ki, float Kd, uint32_t qpps);
+    pub fn set_velocity_pid(
+        &mut self,
+        motor: Motor,
+        pid: VelocityPid,
+    ) -> Result<(), RoboclawError> {
+        let data = [
+            &split_u32_u8(to_fixed_16_16(pid.kd))[..],
+            &split_u32_u8(to_fixed_16_16(pid.kp))[..],
+            &split_u32_u8(to_fixed_16_16(pid.ki))[..],
+            &split_u32_u8(pid.qpps)[..],
+        ]
+        .concat();
+        self.write_command(motor.command(Command::SETM1PID, Command::SETM2PID), data)
+    }
+
+    //uint32_t ReadISpeedM1(uint8_t address,uint8_t *status=NULL,bool *valid=NULL);
+    //uint32_t ReadISpeedM2(uint8_t address,uint8_t *status=NULL,bool *valid=NULL);
+    /// Unfiltered speed of `motor`, measured over the last 1/300th of a
+    /// second and scaled to counts per second.
+    pub fn read_instantaneous_speed(&mut self, motor: Motor) -> Result<Speed, RoboclawError> {
+        self.read_command(motor.command(Command::GETM1ISPEED, Command::GETM2ISPEED), 5)
+            .map(|data| Speed::from_bytes(&data))
+    }
+
+    //bool DutyM1(uint8_t address, uint16_t duty);
     //bool DutyM2(uint8_t address, uint16_t duty);
-    pub fn duty_m2(&mut self, duty: i16) -> std::io::Result<()> {
-        self.write_command(Command::M2DUTY as u8, split_i16_u8(duty).to_vec())
+    pub fn duty(&mut self, motor: Motor, duty: i16) -> Result<(), RoboclawError> {
+        self.write_command(
+            motor.command(Command::M1DUTY, Command::M2DUTY),
+            split_i16_u8(duty).to_vec(),
+        )
+    }
+
+    pub fn duty_m1(&mut self, duty: i16) -> Result<(), RoboclawError> {
+        self.duty(Motor::M1, duty)
+    }
+
+    pub fn duty_m2(&mut self, duty: i16) -> Result<(), RoboclawError> {
+        self.duty(Motor::M2, duty)
     }
 
     //bool DutyM1M2(uint8_t address, uint16_t duty1, uint16_t duty2);
-    pub fn duty_m1_m2(&mut self, duty1: i16, duty2: i16) -> std::io::Result<()> {
+    pub fn duty_m1_m2(&mut self, duty1: i16, duty2: i16) -> Result<(), RoboclawError> {
         self.write_command(
             Command::MIXEDDUTY as u8,
             [&split_i16_u8(duty1)[..], &split_i16_u8(duty2)[..]].concat(),
         )
     }
 
-    /*
-    bool SpeedM1(uint8_t address, uint32_t speed);
-    bool SpeedM2(uint8_t address, uint32_t speed);
-    */
+    //bool SpeedM1(uint8_t address, uint32_t speed);
+    //bool SpeedM2(uint8_t address, uint32_t speed);
+    pub fn speed(&mut self, motor: Motor, speed: i32) -> Result<(), RoboclawError> {
+        self.write_command(
+            motor.command(Command::M1SPEED, Command::M2SPEED),
+            split_i32_u8(speed).to_vec(),
+        )
+    }
+
     //bool SpeedM1M2(uint8_t address, uint32_t speed1, uint32_t speed2);
-    pub fn speed_m1_m2(&mut self, speed_1: i32, speed_2: i32) -> Result<(), std::io::Error> {
+    pub fn speed_m1_m2(&mut self, speed_1: i32, speed_2: i32) -> Result<(), RoboclawError> {
         let speed_1_bytes = split_i32_u8(speed_1);
         let speed_2_bytes = split_i32_u8(speed_2);
         let data = [&speed_1_bytes[..], &speed_2_bytes[..]].concat();
         self.write_command(Command::MIXEDSPEED as u8, data)
     }
-    /*
-    bool SpeedAccelM1(uint8_t address, uint32_t accel, uint32_t speed);
-    bool SpeedAccelM2(uint8_t address, uint32_t accel, uint32_t speed);
-    bool SpeedAccelM1M2(uint8_t address, uint32_t accel, uint32_t speed1, uint32_t speed2);
-    */
-    //bool SpeedDistanceM1(uint8_t address, uint32_t speed, uint32_t distance, uint8_t flag=0);
-    pub fn speed_distance_m1(&mut self, speed: i32, distance: u32) -> Result<(), std::io::Error> {
-        let speed_bytes = split_i32_u8(speed);
-        let distance_bytes = split_u32_u8(distance);
-        let data = [&speed_bytes[..], &distance_bytes[..], &[1u8]].concat();
-        self.write_command(Command::M1SPEEDDIST as u8, data)
+
+    //bool SpeedAccelM1(uint8_t address, uint32_t accel, uint32_t speed);
+    //bool SpeedAccelM2(uint8_t address, uint32_t accel, uint32_t speed);
+    pub fn speed_accel(
+        &mut self,
+        motor: Motor,
+        accel: u32,
+        speed: i32,
+    ) -> Result<(), RoboclawError> {
+        let data = [&split_u32_u8(accel)[..], &split_i32_u8(speed)[..]].concat();
+        self.write_command(
+            motor.command(Command::M1SPEEDACCEL, Command::M2SPEEDACCEL),
+            data,
+        )
+    }
+
+    //bool SpeedAccelM1M2(uint8_t address, uint32_t accel, uint32_t speed1, uint32_t speed2);
+    pub fn speed_accel_m1_m2(
+        &mut self,
+        accel: u32,
+        speed_1: i32,
+        speed_2: i32,
+    ) -> Result<(), RoboclawError> {
+        let data = [
+            &split_u32_u8(accel)[..],
+            &split_i32_u8(speed_1)[..],
+            &split_i32_u8(speed_2)[..],
+        ]
+        .concat();
+        self.write_command(Command::MIXEDSPEEDACCEL as u8, data)
     }
 
+    //bool SpeedDistanceM1(uint8_t address, uint32_t speed, uint32_t distance, uint8_t flag=0);
     //bool SpeedDistanceM2(uint8_t address, uint32_t speed, uint32_t distance, uint8_t flag=0);
-    pub fn speed_distance_m2(&mut self, speed: i32, distance: u32) -> Result<(), std::io::Error> {
+    pub fn speed_distance(
+        &mut self,
+        motor: Motor,
+        speed: i32,
+        distance: u32,
+        buffer: Buffer,
+    ) -> Result<(), RoboclawError> {
         let speed_bytes = split_i32_u8(speed);
         let distance_bytes = split_u32_u8(distance);
-        let data = [&speed_bytes[..], &distance_bytes[..], &[1u8]].concat();
-        self.write_command(Command::M2SPEEDDIST as u8, data)
+        let data = [&speed_bytes[..], &distance_bytes[..]].concat();
+        self.write_buffered_command(
+            motor.command(Command::M1SPEEDDIST, Command::M2SPEEDDIST),
+            data,
+            buffer,
+        )
+    }
+
+    pub fn speed_distance_m1(
+        &mut self,
+        speed: i32,
+        distance: u32,
+        buffer: Buffer,
+    ) -> Result<(), RoboclawError> {
+        self.speed_distance(Motor::M1, speed, distance, buffer)
+    }
+
+    pub fn speed_distance_m2(
+        &mut self,
+        speed: i32,
+        distance: u32,
+        buffer: Buffer,
+    ) -> Result<(), RoboclawError> {
+        self.speed_distance(Motor::M2, speed, distance, buffer)
     }
 
     //bool SpeedDistanceM1M2(uint8_t address, uint32_t speed1, uint32_t distance1, uint32_t speed2, uint32_t distance2, uint8_t flag=0);
@@ -430,7 +1180,8 @@ impl Roboclaw {
         distance_1: u32,
         speed_2: i32,
         distance_2: u32,
-    ) -> Result<(), std::io::Error> {
+        buffer: Buffer,
+    ) -> Result<(), RoboclawError> {
         let speed_1_bytes = split_i32_u8(speed_1);
         let distance_1_bytes = split_u32_u8(distance_1);
         let speed_2_bytes = split_i32_u8(speed_2);
@@ -440,16 +1191,31 @@ impl Roboclaw {
             &distance_1_bytes[..],
             &speed_2_bytes[..],
             &distance_2_bytes[..],
-            &[1u8],
         ]
         .concat();
-        self.write_command(Command::MIXEDSPEEDDIST as u8, data)
+        self.write_buffered_command(Command::MIXEDSPEEDDIST as u8, data, buffer)
     }
 
-    /*
-    bool SpeedAccelDistanceM1(uint8_t address, uint32_t accel, uint32_t speed, uint32_t distance, uint8_t flag=0);
-    bool SpeedAccelDistanceM2(uint8_t address, uint32_t accel, uint32_t speed, uint32_t distance, uint8_t flag=0);
-    */
+    //bool SpeedAccelDistanceM1(uint8_t address, uint32_t accel, uint32_t speed, uint32_t distance, uint8_t flag=0);
+    //bool SpeedAccelDistanceM2(uint8_t address, uint32_t accel, uint32_t speed, uint32_t distance, uint8_t flag=0);
+    pub fn speed_accel_distance(
+        &mut self,
+        motor: Motor,
+        accel: u32,
+        speed: i32,
+        distance: u32,
+        buffer: Buffer,
+    ) -> Result<(), RoboclawError> {
+        let accel_bytes = split_u32_u8(accel);
+        let speed_bytes = split_i32_u8(speed);
+        let distance_bytes = split_u32_u8(distance);
+        let data = [&accel_bytes[..], &speed_bytes[..], &distance_bytes[..]].concat();
+        self.write_buffered_command(
+            motor.command(Command::M1SPEEDACCELDIST, Command::M2SPEEDACCELDIST),
+            data,
+            buffer,
+        )
+    }
 
     //bool SpeedAccelDistanceM1M2(uint8_t address, uint32_t accel, uint32_t speed1, uint32_t distance1, uint32_t speed2, uint32_t distance2, uint8_t flag=0);
     pub fn speed_accel_distance_m1_m2(
@@ -459,7 +1225,8 @@ impl Roboclaw {
         distance_1: u32,
         speed_2: i32,
         distance_2: u32,
-    ) -> Result<(), std::io::Error> {
+        buffer: Buffer,
+    ) -> Result<(), RoboclawError> {
         let accel_bytes = split_u32_u8(accel);
         let speed_1_bytes = split_i32_u8(speed_1);
         let distance_1_bytes = split_u32_u8(distance_1);
@@ -471,14 +1238,13 @@ impl Roboclaw {
             &distance_1_bytes[..],
             &speed_2_bytes[..],
             &distance_2_bytes[..],
-            &[1u8],
         ]
         .concat();
-        self.write_command(Command::MIXEDSPEEDACCELDIST as u8, data)
+        self.write_buffered_command(Command::MIXEDSPEEDACCELDIST as u8, data, buffer)
     }
 
     //bool ReadBuffers(uint8_t address, uint8_t &depth1, uint8_t &depth2);
-    pub fn read_buffers(&mut self) -> std::io::Result<(BufferStatus, BufferStatus)> {
+    pub fn read_buffers(&mut self) -> Result<(BufferStatus, BufferStatus), RoboclawError> {
         self.read_command(Command::GETBUFFERS as u8, 2).map(|data| {
             (
                 match data[0] {
@@ -494,23 +1260,124 @@ impl Roboclaw {
             )
         })
     }
+
+    //bool ReadPWMs(uint8_t address, int16_t &pwm1, int16_t &pwm2);
+    /// Duty cycles currently applied to both motors, from -32767 to +32767.
+    pub fn read_pwms(&mut self) -> Result<(i16, i16), RoboclawError> {
+        self.read_command(Command::GETPWMS as u8, 4).map(|data| {
+            (
+                join_u8(data[0], data[1]) as i16,
+                join_u8(data[2], data[3]) as i16,
+            )
+        })
+    }
+
+    //bool ReadCurrents(uint8_t address, int16_t &current1, int16_t &current2);
+    /// Motor currents in amps.
+    pub fn read_currents(&mut self) -> Result<(f32, f32), RoboclawError> {
+        self.read_command(Command::GETCURRENTS as u8, 4)
+            .map(|data| {
+                (
+                    join_u8(data[0], data[1]) as i16 as f32 / 100.0,
+                    join_u8(data[2], data[3]) as i16 as f32 / 100.0,
+                )
+            })
+    }
+
+    //bool SpeedAccelM1M2_2(uint8_t address, uint32_t accel1, uint32_t speed1, uint32_t accel2, uint32_t speed2);
+    pub fn speed_accel_m1_m2_2(
+        &mut self,
+        accel_1: u32,
+        speed_1: i32,
+        accel_2: u32,
+        speed_2: i32,
+    ) -> Result<(), RoboclawError> {
+        let data = [
+            &split_u32_u8(accel_1)[..],
+            &split_i32_u8(speed_1)[..],
+            &split_u32_u8(accel_2)[..],
+            &split_i32_u8(speed_2)[..],
+        ]
+        .concat();
+        self.write_command(Command::MIXEDSPEED2ACCEL as u8, data)
+    }
+
     /*
-    bool ReadPWMs(uint8_t address, int16_t &pwm1, int16_t &pwm2);
-    bool ReadCurrents(uint8_t address, int16_t &current1, int16_t &current2);
-    bool SpeedAccelM1M2_2(uint8_t address, uint32_t accel1, uint32_t speed1, uint32_t accel2, uint32_t speed2);
     bool SpeedAccelDistanceM1M2_2(uint8_t address, uint32_t accel1, uint32_t speed1, uint32_t distance1, uint32_t accel2, uint32_t speed2, uint32_t distance2, uint8_t flag=0);
-    bool DutyAccelM1(uint8_t address, uint16_t duty, uint32_t accel);
-    bool DutyAccelM2(uint8_t address, uint16_t duty, uint32_t accel);
-    bool DutyAccelM1M2(uint8_t address, uint16_t duty1, uint32_t accel1, uint16_t duty2, uint32_t accel2);
-    bool ReadM1VelocityPID(uint8_t address,float &Kp_fp,float &Ki_fp,float &Kd_fp,uint32_t &qpps);
-    bool ReadM2VelocityPID(uint8_t address,float &Kp_fp,float &Ki_fp,float &Kd_fp,uint32_t &qpps);
-    bool SetMainVoltages(uint8_t address,uint16_t min,uint16_t max);
-    bool SetLogicVoltages(uint8_t address,uint16_t min,uint16_t max);
     */
 
+    //bool DutyAccelM1(uint8_t address, uint16_t duty, uint32_t accel);
+    //bool DutyAccelM2(uint8_t address, uint16_t duty, uint32_t accel);
+    pub fn duty_accel(&mut self, motor: Motor, duty: i16, accel: u32) -> Result<(), RoboclawError> {
+        let data = [&split_i16_u8(duty)[..], &split_u32_u8(accel)[..]].concat();
+        self.write_command(
+            motor.command(Command::M1DUTYACCEL, Command::M2DUTYACCEL),
+            data,
+        )
+    }
+
+    //bool DutyAccelM1M2(uint8_t address, uint16_t duty1, uint32_t accel1, uint16_t duty2, uint32_t accel2);
+    pub fn duty_accel_m1_m2(
+        &mut self,
+        duty_1: i16,
+        accel_1: u32,
+        duty_2: i16,
+        accel_2: u32,
+    ) -> Result<(), RoboclawError> {
+        let data = [
+            &split_i16_u8(duty_1)[..],
+            &split_u32_u8(accel_1)[..],
+            &split_i16_u8(duty_2)[..],
+            &split_u32_u8(accel_2)[..],
+        ]
+        .concat();
+        self.write_command(Command::MIXEDDUTYACCEL as u8, data)
+    }
+
+    //bool ReadM1VelocityPID(uint8_t address,float &Kp_fp,float &Ki_fp,float &Kd_fp,uint32_t &qpps);
+    //bool ReadM2VelocityPID(uint8_t address,float &Kp_fp,float &Ki_fp,float &Kd_fp,uint32_t &qpps);
+    pub fn read_velocity_pid(&mut self, motor: Motor) -> Result<VelocityPid, RoboclawError> {
+        self.read_command(motor.command(Command::READM1PID, Command::READM2PID), 16)
+            .map(|data| VelocityPid {
+                kp: from_fixed_16_16(join_u8_u32(data[0], data[1], data[2], data[3])),
+                ki: from_fixed_16_16(join_u8_u32(data[4], data[5], data[6], data[7])),
+                kd: from_fixed_16_16(join_u8_u32(data[8], data[9], data[10], data[11])),
+                qpps: join_u8_u32(data[12], data[13], data[14], data[15]),
+            })
+    }
+
+    //bool SetMainVoltages(uint8_t address,uint16_t min,uint16_t max);
+    /// Sets the main battery voltages below and above which the controller
+    /// stops the motors. Both must be within [`MAIN_VOLTAGE_RANGE`], or
+    /// [`HV_MAIN_VOLTAGE_RANGE`] on HV boards. A maximum above 34V has the
+    /// model read first, costing one extra packet on the first call.
+    pub fn set_main_voltages(&mut self, min: f32, max: f32) -> Result<(), RoboclawError> {
+        let command_code = Command::SETMAINVOLTAGES as u8;
+        let range = if max > *MAIN_VOLTAGE_RANGE.end()
+            && self.model()?.is_some_and(|model| model.high_voltage)
+        {
+            HV_MAIN_VOLTAGE_RANGE
+        } else {
+            MAIN_VOLTAGE_RANGE
+        };
+        let data = voltage_limits(command_code, min, max, range)?;
+        self.write_command(command_code, data)
+    }
+
+    //bool SetLogicVoltages(uint8_t address,uint16_t min,uint16_t max);
+    /// Sets the logic battery voltages below and above which the controller
+    /// stops the motors. Both must be within [`LOGIC_VOLTAGE_RANGE`], and the
+    /// minimum within [`LOGIC_MIN_VOLTAGE_RANGE`].
+    pub fn set_logic_voltages(&mut self, min: f32, max: f32) -> Result<(), RoboclawError> {
+        let command_code = Command::SETLOGICVOLTAGES as u8;
+        check_voltage(command_code, min, LOGIC_MIN_VOLTAGE_RANGE)?;
+        let data = voltage_limits(command_code, min, max, LOGIC_VOLTAGE_RANGE)?;
+        self.write_command(command_code, data)
+    }
+
     //bool ReadMinMaxMainVoltages(uint8_t address,uint16_t &min,uint16_t &max);
 
-    pub fn read_min_max_main_voltages(&mut self) -> Result<(f32, f32), std::io::Error> {
+    pub fn read_min_max_main_voltages(&mut self) -> Result<(f32, f32), RoboclawError> {
         self.read_command(Command::GETMINMAXMAINVOLTAGES as u8, 4)
             .map(|data| {
                 (
@@ -520,15 +1387,95 @@ impl Roboclaw {
             })
     }
 
-    /*
-    bool ReadMinMaxLogicVoltages(uint8_t address,uint16_t &min,uint16_t &max);
-    bool SetM1PositionPID(uint8_t address,float kp,float ki,float kd,uint32_t kiMax,uint32_t deadzone,uint32_t min,uint32_t max);
-    bool SetM2PositionPID(uint8_t address,float kp,float ki,float kd,uint32_t kiMax,uint32_t deadzone,uint32_t min,uint32_t max);
-    bool ReadM1PositionPID(uint8_t address,float &Kp,float &Ki,float &Kd,uint32_t &KiMax,uint32_t &DeadZone,uint32_t &Min,uint32_t &Max);
-    bool ReadM2PositionPID(uint8_t address,float &Kp,float &Ki,float &Kd,uint32_t &KiMax,uint32_t &DeadZone,uint32_t &Min,uint32_t &Max);
-    bool SpeedAccelDeccelPositionM1(uint8_t address,uint32_t accel,uint32_t speed,uint32_t deccel,uint32_t position,uint8_t flag);
-    bool SpeedAccelDeccelPositionM2(uint8_t address,uint32_t accel,uint32_t speed,uint32_t deccel,uint32_t position,uint8_t flag);
-    */
+    //bool ReadMinMaxLogicVoltages(uint8_t address,uint16_t &min,uint16_t &max);
+    pub fn read_min_max_logic_voltages(&mut self) -> Result<(f32, f32), RoboclawError> {
+        self.read_command(Command::GETMINMAXLOGICVOLTAGES as u8, 4)
+            .map(|data| {
+                (
+                    join_u8(data[0], data[1]) as f32 / 10.0,
+                    join_u8(data[2], data[3]) as f32 / 10.0,
+                )
+            })
+    }
+
+    //bool SetM1PositionPID(uint8_t address,float kp,float ki,float kd,uint32_t kiMax,uint32_t deadzone,uint32_t min,uint32_t max);
+    //bool SetM2PositionPID(uint8_t address,float kp,float ki,float kd,uint32_t kiMax,uint32_t deadzone,uint32_t min,uint32_t max);
+    pub fn set_position_pid(
+        &mut self,
+        motor: Motor,
+        pid: PositionPid,
+    ) -> Result<(), RoboclawError> {
+        let data = [
+            split_u32_u8(to_fixed_22_10(pid.kd)),
+            split_u32_u8(to_fixed_22_10(pid.kp)),
+            split_u32_u8(to_fixed_22_10(pid.ki)),
+            split_u32_u8(pid.ki_max),
+            split_u32_u8(pid.deadzone),
+            split_i32_u8(pid.min),
+            split_i32_u8(pid.max),
+        ]
+        .concat();
+        self.write_command(
+            motor.command(Command::SETM1POSPID, Command::SETM2POSPID),
+            data,
+        )
+    }
+
+    //bool ReadM1PositionPID(uint8_t address,float &Kp,float &Ki,float &Kd,uint32_t &KiMax,uint32_t &DeadZone,uint32_t &Min,uint32_t &Max);
+    //bool ReadM2PositionPID(uint8_t address,float &Kp,float &Ki,float &Kd,uint32_t &KiMax,uint32_t &DeadZone,uint32_t &Min,uint32_t &Max);
+    pub fn read_position_pid(&mut self, motor: Motor) -> Result<PositionPid, RoboclawError> {
+        self.read_command(
+            motor.command(Command::READM1POSPID, Command::READM2POSPID),
+            28,
+        )
+        .map(|data| {
+            let word = |i: usize| join_u8_u32(data[i], data[i + 1], data[i + 2], data[i + 3]);
+            PositionPid {
+                kp: from_fixed_22_10(word(0)),
+                ki: from_fixed_22_10(word(4)),
+                kd: from_fixed_22_10(word(8)),
+                ki_max: word(12),
+                deadzone: word(16),
+                min: word(20) as i32,
+                max: word(24) as i32,
+            }
+        })
+    }
+
+    //bool SpeedAccelDeccelPositionM1(uint8_t address,uint32_t accel,uint32_t speed,uint32_t deccel,uint32_t position,uint8_t flag);
+    //bool SpeedAccelDeccelPositionM2(uint8_t address,uint32_t accel,uint32_t speed,uint32_t deccel,uint32_t position,uint8_t flag);
+    /// Moves `motor` to `position`, leaving the other channel alone.
+    pub fn speed_accel_deccel_position(
+        &mut self,
+        motor: Motor,
+        accel: u32,
+        speed: i32,
+        deccel: u32,
+        position: u32,
+        buffer: Buffer,
+    ) -> Result<(), RoboclawError> {
+        let accel_bytes = split_u32_u8(accel);
+        let speed_bytes = split_i32_u8(speed);
+        let deccel_bytes = split_u32_u8(deccel);
+        let position_bytes = split_u32_u8(position);
+
+        let data = [
+            &accel_bytes[..],
+            &speed_bytes[..],
+            &deccel_bytes[..],
+            &position_bytes[..],
+        ]
+        .concat();
+        self.write_buffered_command(
+            motor.command(
+                Command::M1SPEEDACCELDECCELPOS,
+                Command::M2SPEEDACCELDECCELPOS,
+            ),
+            data,
+            buffer,
+        )
+    }
+
     //bool SpeedAccelDeccelPositionM1M2(uint8_t address,uint32_t accel1,uint32_t speed1,uint32_t deccel1,uint32_t position1,uint32_t accel2,uint32_t speed2,uint32_t deccel2,uint32_t position2,uint8_t flag);
     #[allow(clippy::too_many_arguments)]
     pub fn speed_accel_deccel_position_m1_m2(
@@ -541,7 +1488,8 @@ impl Roboclaw {
         speed_2: i32,
         deccel_2: u32,
         position_2: u32,
-    ) -> Result<(), std::io::Error> {
+        buffer: Buffer,
+    ) -> Result<(), RoboclawError> {
         let accel_1_bytes = split_u32_u8(accel_1);
         let speed_1_bytes = split_i32_u8(speed_1);
         let deccel_1_bytes = split_u32_u8(deccel_1);
@@ -561,22 +1509,71 @@ impl Roboclaw {
             &speed_2_bytes[..],
             &deccel_2_bytes[..],
             &position_2_bytes[..],
-            &[1u8],
         ]
         .concat();
-        self.write_command(Command::MIXEDSPEEDACCELDECCELPOS as u8, data)
+        self.write_buffered_command(Command::MIXEDSPEEDACCELDECCELPOS as u8, data, buffer)
     }
 
-    /*
-    bool SetM1DefaultAccel(uint8_t address, uint32_t accel);
-    bool SetM2DefaultAccel(uint8_t address, uint32_t accel);
-    bool SetPinFunctions(uint8_t address, uint8_t S3mode, uint8_t S4mode, uint8_t S5mode);
-    bool GetPinFunctions(uint8_t address, uint8_t &S3mode, uint8_t &S4mode, uint8_t &S5mode);
-    bool SetDeadBand(uint8_t address, uint8_t Min, uint8_t Max);
-    bool GetDeadBand(uint8_t address, uint8_t &Min, uint8_t &Max);
-    */
+    //bool SetM1DefaultAccel(uint8_t address, uint32_t accel);
+    //bool SetM2DefaultAccel(uint8_t address, uint32_t accel);
+    /// Sets the acceleration used by commands that don't take one, such as
+    /// [`duty`](Self::duty) and the RC and analog modes.
+    pub fn set_default_accel(&mut self, motor: Motor, accel: u32) -> Result<(), RoboclawError> {
+        self.write_command(
+            motor.command(Command::SETM1DEFAULTACCEL, Command::SETM2DEFAULTACCEL),
+            split_u32_u8(accel).to_vec(),
+        )
+    }
+
+    //bool SetPinFunctions(uint8_t address, uint8_t S3mode, uint8_t S4mode, uint8_t S5mode);
+    pub fn set_pin_functions(&mut self, functions: PinFunctions) -> Result<(), RoboclawError> {
+        self.write_command(
+            Command::SETPINFUNCTIONS as u8,
+            vec![functions.s3 as u8, functions.s4 as u8, functions.s5 as u8],
+        )
+    }
+
+    //bool GetPinFunctions(uint8_t address, uint8_t &S3mode, uint8_t &S4mode, uint8_t &S5mode);
+    pub fn read_pin_functions(&mut self) -> Result<PinFunctions, RoboclawError> {
+        let data = self.read_command(Command::GETPINFUNCTIONS as u8, 3)?;
+        match (
+            S3Mode::try_from(data[0]),
+            SwitchMode::try_from(data[1]),
+            SwitchMode::try_from(data[2]),
+        ) {
+            (Ok(s3), Ok(s4), Ok(s5)) => Ok(PinFunctions { s3, s4, s5 }),
+            _ => Err(RoboclawError::InvalidResponse {
+                command: Command::GETPINFUNCTIONS as u8,
+                data,
+            }),
+        }
+    }
+
+    //bool SetDeadBand(uint8_t address, uint8_t Min, uint8_t Max);
+    /// Sets the RC and analog deadbands in reverse and forward, in percent
+    /// of full scale from 0 to 25 in steps of 0.1.
+    pub fn set_deadband(&mut self, min: f32, max: f32) -> Result<(), RoboclawError> {
+        for percent in [min, max] {
+            if !(0.0..=25.0).contains(&percent) {
+                return Err(RoboclawError::InvalidArgument {
+                    command: Command::SETDEADBAND as u8,
+                    reason: format!("{}% is outside 0% to 25%", percent),
+                });
+            }
+        }
+        self.write_command(
+            Command::SETDEADBAND as u8,
+            vec![(min * 10.0).round() as u8, (max * 10.0).round() as u8],
+        )
+    }
+
+    //bool GetDeadBand(uint8_t address, uint8_t &Min, uint8_t &Max);
+    pub fn read_deadband(&mut self) -> Result<(f32, f32), RoboclawError> {
+        self.read_command(Command::GETDEADBAND as u8, 2)
+            .map(|data| (data[0] as f32 / 10.0, data[1] as f32 / 10.0))
+    }
     //bool ReadEncoders(uint8_t address,uint32_t &enc1,uint32_t &enc2);
-    pub fn read_encoders(&mut self) -> Result<(u32, u32), std::io::Error> {
+    pub fn read_encoders(&mut self) -> Result<(u32, u32), RoboclawError> {
         self.read_command(Command::GETENCODERS as u8, 8)
             .map(|data| {
                 (
@@ -586,39 +1583,162 @@ impl Roboclaw {
             })
     }
 
-    /*
-    bool ReadISpeeds(uint8_t address,uint32_t &ispeed1,uint32_t &ispeed2);
-    bool RestoreDefaults(uint8_t address);
-    bool ReadTemp(uint8_t address, uint16_t &temp);
-    bool ReadTemp2(uint8_t address, uint16_t &temp);
-    */
+    //bool ReadISpeeds(uint8_t address,uint32_t &ispeed1,uint32_t &ispeed2);
+    /// Unfiltered speeds of both motors in counts per second, negative when
+    /// running backwards.
+    pub fn read_instantaneous_speeds(&mut self) -> Result<(i32, i32), RoboclawError> {
+        self.read_command(Command::GETISPEEDS as u8, 8).map(|data| {
+            (
+                join_u8_u32(data[0], data[1], data[2], data[3]) as i32,
+                join_u8_u32(data[4], data[5], data[6], data[7]) as i32,
+            )
+        })
+    }
+
+    //bool RestoreDefaults(uint8_t address);
+    /// Resets every setting to its factory default. This includes the
+    /// serial mode and baud rate, so the connection may be lost.
+    pub fn restore_defaults(&mut self, _confirm: Confirm) -> Result<(), RoboclawError> {
+        self.write_simple_command(Command::RESTOREDEFAULTS as u8)
+    }
+
+    //bool ReadTemp(uint8_t address, uint16_t &temp);
+    /// Board temperature in degrees Celsius.
+    pub fn read_temperature(&mut self) -> Result<f32, RoboclawError> {
+        self.read_command(Command::GETTEMP as u8, 2)
+            .map(|data| (join_u8(data[0], data[1]) as f32) / 10.0)
+    }
+
+    //bool ReadTemp2(uint8_t address, uint16_t &temp);
+    /// Reading of the second temperature sensor in degrees Celsius.
+    ///
+    /// Only the bigger boards have a second sensor, so the model is checked
+    /// first (costing one extra packet on the first call) and
+    /// [`RoboclawError::Unsupported`] returned for the others. Boards whose
+    /// model isn't recognised are asked anyway.
+    pub fn read_temperature2(&mut self) -> Result<f32, RoboclawError> {
+        if self
+            .model()?
+            .is_some_and(|model| !model.has_second_temperature_sensor())
+        {
+            return Err(RoboclawError::Unsupported {
+                command: Command::GETTEMP2 as u8,
+            });
+        }
+        self.read_command(Command::GETTEMP2 as u8, 2)
+            .map(|data| (join_u8(data[0], data[1]) as f32) / 10.0)
+    }
 
     //uint16_t ReadError(uint8_t address,bool *valid=NULL);
-    pub fn read_error(&mut self) -> Result<StatusFlags, std::io::Error> {
-        self.read_command(Command::GETERROR as u8, 4)
-            .map(|data| StatusFlags::from_bits(join_u8_u32(data[0], data[1], data[2], data[3])).unwrap())
+    pub fn read_error(&mut self) -> Result<StatusFlags, RoboclawError> {
+        let data = self.read_command(Command::GETERROR as u8, 4)?;
+        // Newer firmware sets bits not listed here, so leave those out
+        // rather than rejecting the whole status.
+        Ok(StatusFlags::from_bits_truncate(join_u8_u32(
+            data[0], data[1], data[2], data[3],
+        )))
+    }
+
+    //bool ReadEncoderModes(uint8_t address, uint8_t &M1mode, uint8_t &M2mode);
+    pub fn read_encoder_modes(&mut self) -> Result<(EncoderMode, EncoderMode), RoboclawError> {
+        self.read_command(Command::GETENCODERMODE as u8, 2)
+            .map(|data| {
+                (
+                    EncoderMode::from_bits_truncate(data[0]),
+                    EncoderMode::from_bits_truncate(data[1]),
+                )
+            })
+    }
+
+    //bool SetM1EncoderMode(uint8_t address,uint8_t mode);
+    //bool SetM2EncoderMode(uint8_t address,uint8_t mode);
+    pub fn set_encoder_mode(
+        &mut self,
+        motor: Motor,
+        mode: EncoderMode,
+    ) -> Result<(), RoboclawError> {
+        self.write_command(
+            motor.command(Command::SETM1ENCODERMODE, Command::SETM2ENCODERMODE),
+            vec![mode.bits()],
+        )
+    }
+
+    //bool WriteNVM(uint8_t address);
+    /// Saves the current settings so they're loaded at power up, replacing
+    /// whatever was saved before.
+    pub fn write_nvm(&mut self, _confirm: Confirm) -> Result<(), RoboclawError> {
+        self.write_command(Command::WRITENVM as u8, split_u32_u8(NVM_KEY).to_vec())
+    }
+
+    //bool ReadNVM(uint8_t address);
+    /// Reloads the saved settings, discarding any changes made since.
+    pub fn read_nvm(&mut self) -> Result<(), RoboclawError> {
+        self.write_simple_command(Command::READNVM as u8)
+    }
+
+    //bool SetConfig(uint8_t address, uint16_t config);
+    /// Changes the configuration. Use [`write_nvm`](Self::write_nvm) to keep
+    /// it across power cycles.
+    pub fn set_config(&mut self, config: &Config) -> Result<(), RoboclawError> {
+        let bits = config
+            .bits()
+            .ok_or_else(|| RoboclawError::InvalidArgument {
+                command: Command::SETCONFIG as u8,
+                reason: format!(
+                    "{:?} has a packet address outside 0x80 to 0x87",
+                    config.control_mode
+                ),
+            })?;
+        self.write_command(Command::SETCONFIG as u8, split_u16_u8(bits).to_vec())
     }
 
-    /*
-    bool ReadEncoderModes(uint8_t address, uint8_t &M1mode, uint8_t &M2mode);
-    bool SetM1EncoderMode(uint8_t address,uint8_t mode);
-    bool SetM2EncoderMode(uint8_t address,uint8_t mode);
-    bool WriteNVM(uint8_t address);
-    bool ReadNVM(uint8_t address);
-    bool SetConfig(uint8_t address, uint16_t config);
-    */
     //bool GetConfig(uint8_t address, uint16_t &config);
-    pub fn get_config(&mut self) -> Result<ConfigFlags, std::io::Error> {
+    pub fn get_config(&mut self) -> Result<Config, RoboclawError> {
         self.read_command(Command::GETCONFIG as u8, 2)
-            .map(|data| ConfigFlags::from_bits(join_u8(data[0], data[1])).unwrap())
+            .map(|data| Config::from_bits(join_u8(data[0], data[1])))
     }
 
-    /*
-    bool SetM1MaxCurrent(uint8_t address,uint32_t max);
-    bool SetM2MaxCurrent(uint8_t address,uint32_t max);
-    bool ReadM1MaxCurrent(uint8_t address,uint32_t &max);
-    bool ReadM2MaxCurrent(uint8_t address,uint32_t &max);
-    bool SetPWMMode(uint8_t address, uint8_t mode);
-    bool GetPWMMode(uint8_t address, uint8_t &mode);
-    */
+    //bool SetM1MaxCurrent(uint8_t address,uint32_t max);
+    //bool SetM2MaxCurrent(uint8_t address,uint32_t max);
+    /// Limits the current of `motor`, in amps. The controller reduces the
+    /// duty cycle to stay below it.
+    pub fn set_max_current(&mut self, motor: Motor, amps: f32) -> Result<(), RoboclawError> {
+        let command_code = motor.command(Command::SETM1MAXCURRENT, Command::SETM2MAXCURRENT);
+        if !(amps.is_finite() && amps >= 0.0) {
+            return Err(RoboclawError::InvalidArgument {
+                command: command_code,
+                reason: format!("{}A is not a current limit", amps),
+            });
+        }
+        // The wire unit is 10mA, followed by a minimum current that's always
+        // left at zero.
+        let max_bytes = split_u32_u8((amps * 100.0).round() as u32);
+        let min_bytes = split_u32_u8(0);
+        self.write_command(command_code, [&max_bytes[..], &min_bytes[..]].concat())
+    }
+
+    //bool ReadM1MaxCurrent(uint8_t address,uint32_t &max);
+    //bool ReadM2MaxCurrent(uint8_t address,uint32_t &max);
+    /// Current limit of `motor`, in amps.
+    pub fn read_max_current(&mut self, motor: Motor) -> Result<f32, RoboclawError> {
+        self.read_command(
+            motor.command(Command::GETM1MAXCURRENT, Command::GETM2MAXCURRENT),
+            8,
+        )
+        .map(|data| join_u8_u32(data[0], data[1], data[2], data[3]) as f32 / 100.0)
+    }
+
+    //bool SetPWMMode(uint8_t address, uint8_t mode);
+    pub fn set_pwm_mode(&mut self, mode: PwmMode) -> Result<(), RoboclawError> {
+        self.write_command(Command::SETPWMMODE as u8, vec![mode as u8])
+    }
+
+    //bool GetPWMMode(uint8_t address, uint8_t &mode);
+    pub fn read_pwm_mode(&mut self) -> Result<PwmMode, RoboclawError> {
+        let data = self.read_command(Command::GETPWMMODE as u8, 1)?;
+        PwmMode::try_from(data[0]).map_err(|_| RoboclawError::InvalidResponse {
+            command: Command::GETPWMMODE as u8,
+            data,
+        })
+    }
 }
@@ -0,0 +1,69 @@
+use crate::RoboclawError;
+use std::time::Duration;
+
+/// How packet exchanges that fail with a transient error are retried.
+///
+/// CRC mismatches, timeouts and garbled acknowledgements are retried; other
+/// transport errors are returned straight away. Motion commands queued with
+/// [`Buffer::Queue`](crate::Buffer::Queue) are never retried, since resending
+/// one whose acknowledgement got lost would queue it twice.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Tries per packet, including the first one.
+    pub attempts: u32,
+    /// Whether to discard stale bytes in the transport before each attempt,
+    /// so that a late reply to an earlier packet isn't taken for this one's.
+    pub flush: bool,
+    /// Pause before the first retry, doubled before every further one.
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Sends every packet exactly once, still flushing before it.
+    pub fn none() -> Self {
+        RetryPolicy {
+            attempts: 1,
+            flush: true,
+            backoff: Duration::ZERO,
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    /// Three attempts with a flush and a short pause in between, similar to
+    /// the Basicmicro reference libraries.
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 3,
+            flush: true,
+            backoff: Duration::from_millis(1),
+        }
+    }
+}
+
+/// Link quality counters kept by each [`Roboclaw`](crate::Roboclaw) handle.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RetryStats {
+    /// Packet exchanges started, not counting retries.
+    pub packets: u64,
+    /// Packets sent again after a transient error.
+    pub retries: u64,
+    /// Exchanges that still failed after the last attempt.
+    pub failures: u64,
+    pub crc_errors: u64,
+    pub timeouts: u64,
+    pub nacks: u64,
+}
+
+impl RetryStats {
+    /// Counts `error` and returns whether it's worth retrying.
+    pub(crate) fn record(&mut self, error: &RoboclawError) -> bool {
+        match error {
+            RoboclawError::Crc { .. } => self.crc_errors += 1,
+            RoboclawError::Timeout => self.timeouts += 1,
+            RoboclawError::Nack { .. } => self.nacks += 1,
+            _ => return false,
+        }
+        true
+    }
+}
@@ -0,0 +1,1064 @@
+use crate::transport::Transport;
+use crate::{
+    join_u8, join_u8_u32, split_i16_u8, split_i32_u8, split_u16_u8, split_u32_u8, Command,
+    StatusFlags, DEFAULT_ADDRESS, NVM_KEY,
+};
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Number of buffered motion commands each channel can hold.
+const BUFFER_DEPTH: usize = 32;
+/// Physics integration step, in seconds.
+const STEP: f64 = 0.001;
+const DUTY_MAX: f64 = 32767.0;
+/// Current drawn by an unloaded channel at full duty, in amps.
+const FULL_DUTY_CURRENT: f64 = 2.0;
+
+const ENCODER_UNDERFLOW: u8 = 0x01;
+const ENCODER_BACKWARD: u8 = 0x02;
+const ENCODER_OVERFLOW: u8 = 0x04;
+
+#[derive(Clone, Copy, Default)]
+struct VelocityPid {
+    p: u32,
+    i: u32,
+    d: u32,
+    qpps: u32,
+}
+
+#[derive(Clone, Copy, Default)]
+struct PositionPid {
+    p: u32,
+    i: u32,
+    d: u32,
+    i_max: u32,
+    deadzone: u32,
+    min: u32,
+    max: u32,
+}
+
+/// Everything the controller keeps in non-volatile memory. Voltages are in
+/// tenths of a volt, currents in tens of milliamps.
+#[derive(Clone)]
+struct Settings {
+    main_voltage_limits: (u16, u16),
+    logic_voltage_limits: (u16, u16),
+    velocity_pid: [VelocityPid; 2],
+    position_pid: [PositionPid; 2],
+    default_accel: [u32; 2],
+    pin_functions: [u8; 3],
+    deadband: (u8, u8),
+    encoder_mode: [u8; 2],
+    config: u16,
+    max_current: [(u32, u32); 2],
+    pwm_mode: u8,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let velocity_pid = VelocityPid {
+            p: 0x0001_0000,
+            i: 0x0000_8000,
+            d: 0x0000_4000,
+            qpps: 10_000,
+        };
+        Settings {
+            main_voltage_limits: (60, 340),
+            logic_voltage_limits: (60, 340),
+            velocity_pid: [velocity_pid; 2],
+            position_pid: [PositionPid::default(); 2],
+            default_accel: [0; 2],
+            pin_functions: [0; 3],
+            deadband: (25, 25),
+            encoder_mode: [0; 2],
+            // Packet serial, automatic battery detection, 38400 baud.
+            config: 0x0067,
+            max_current: [(1500, 0); 2],
+            pwm_mode: 1,
+        }
+    }
+}
+
+/// A buffered motion command.
+#[derive(Clone, Copy)]
+enum Segment {
+    Distance {
+        accel: u32,
+        speed: i32,
+        distance: u32,
+    },
+    Position {
+        accel: u32,
+        speed: u32,
+        deccel: u32,
+        position: i32,
+    },
+}
+
+/// What a channel is currently trying to do. Accelerations of zero mean
+/// "change instantly".
+#[derive(Clone, Copy)]
+enum Drive {
+    Duty {
+        target: f64,
+        accel: f64,
+    },
+    Speed {
+        target: f64,
+        accel: f64,
+    },
+    Distance {
+        target: f64,
+        accel: f64,
+        remaining: f64,
+    },
+    Position {
+        accel: f64,
+        speed: f64,
+        deccel: f64,
+        target: f64,
+    },
+}
+
+impl From<Segment> for Drive {
+    fn from(segment: Segment) -> Self {
+        match segment {
+            Segment::Distance {
+                accel,
+                speed,
+                distance,
+            } => Drive::Distance {
+                target: speed as f64,
+                accel: accel as f64,
+                remaining: distance as f64,
+            },
+            Segment::Position {
+                accel,
+                speed,
+                deccel,
+                position,
+            } => Drive::Position {
+                accel: accel as f64,
+                speed: (speed as i32).unsigned_abs() as f64,
+                deccel: deccel as f64,
+                target: position as f64,
+            },
+        }
+    }
+}
+
+/// Moves `value` towards `target` by at most `rate * dt`.
+fn ramp(value: f64, target: f64, rate: f64, dt: f64) -> f64 {
+    if rate <= 0.0 {
+        return target;
+    }
+    let step = rate * dt;
+    if (target - value).abs() <= step {
+        target
+    } else {
+        value + step.copysign(target - value)
+    }
+}
+
+struct Channel {
+    count: u32,
+    residual: f64,
+    speed: f64,
+    duty: f64,
+    encoder_status: u8,
+    drive: Drive,
+    /// Whether `drive` came from the buffer and hasn't finished yet.
+    executing: bool,
+    queue: VecDeque<Segment>,
+}
+
+impl Default for Channel {
+    fn default() -> Self {
+        Channel {
+            count: 0,
+            residual: 0.0,
+            speed: 0.0,
+            duty: 0.0,
+            encoder_status: 0,
+            drive: Drive::Duty {
+                target: 0.0,
+                accel: 0.0,
+            },
+            executing: false,
+            queue: VecDeque::new(),
+        }
+    }
+}
+
+impl Channel {
+    fn position(&self) -> f64 {
+        self.count as i32 as f64 + self.residual
+    }
+
+    /// Replaces whatever the channel is doing, discarding buffered commands.
+    fn set_drive(&mut self, drive: Drive) {
+        self.queue.clear();
+        self.executing = false;
+        self.drive = drive;
+    }
+
+    fn buffer_full(&self, flag: u8) -> bool {
+        flag == 0 && self.executing && self.queue.len() >= BUFFER_DEPTH
+    }
+
+    /// Buffers a motion command. A flag of 0 queues it behind the commands
+    /// already waiting, anything else discards them and starts it now.
+    fn push(&mut self, segment: Segment, flag: u8) {
+        if flag != 0 {
+            self.queue.clear();
+            self.executing = false;
+        }
+        if self.executing {
+            self.queue.push_back(segment);
+        } else {
+            self.drive = segment.into();
+            self.executing = true;
+        }
+    }
+
+    fn finish_segment(&mut self) {
+        match self.queue.pop_front() {
+            Some(segment) => self.drive = segment.into(),
+            None => {
+                self.executing = false;
+                if let Drive::Distance { accel, .. } = self.drive {
+                    // Stop on the last count rather than coasting past it.
+                    self.speed = 0.0;
+                    self.duty = 0.0;
+                    self.drive = Drive::Speed { target: 0.0, accel };
+                }
+            }
+        }
+    }
+
+    fn buffer_status(&self) -> u8 {
+        if self.executing {
+            self.queue.len() as u8
+        } else {
+            0x80
+        }
+    }
+
+    fn step(&mut self, qpps: f64, dt: f64) {
+        match self.drive {
+            Drive::Duty { target, accel } => {
+                self.duty = ramp(self.duty, target, accel, dt);
+                self.speed = self.duty / DUTY_MAX * qpps;
+            }
+            Drive::Speed { target, accel } => {
+                self.speed = ramp(self.speed, target, accel, dt);
+            }
+            Drive::Distance {
+                target,
+                accel,
+                remaining,
+            } => {
+                self.speed = ramp(self.speed, target, accel, dt);
+                let travel = (self.speed * dt).abs();
+                if travel >= remaining {
+                    // Only move as far as the segment has left to go.
+                    self.integrate(remaining.copysign(self.speed));
+                    self.finish_segment();
+                    return;
+                }
+                self.drive = Drive::Distance {
+                    target,
+                    accel,
+                    remaining: remaining - travel,
+                };
+            }
+            Drive::Position {
+                accel,
+                speed,
+                deccel,
+                target,
+            } => {
+                let error = target - self.position();
+                if error.abs() <= (self.speed * dt).abs().max(0.5) {
+                    self.count = target as i32 as u32;
+                    self.residual = 0.0;
+                    self.speed = 0.0;
+                    self.duty = 0.0;
+                    if self.executing {
+                        self.finish_segment();
+                    }
+                    return;
+                }
+                let mut cruise = speed;
+                if deccel > 0.0 {
+                    cruise = cruise.min((2.0 * deccel * error.abs()).sqrt());
+                }
+                let desired = cruise.copysign(error);
+                let rate = if desired.abs() < self.speed.abs() {
+                    deccel
+                } else {
+                    accel
+                };
+                self.speed = ramp(self.speed, desired, rate, dt);
+            }
+        }
+        if !matches!(self.drive, Drive::Duty { .. }) {
+            self.duty = (self.speed / qpps * DUTY_MAX).clamp(-DUTY_MAX, DUTY_MAX);
+        }
+        self.integrate(self.speed * dt);
+    }
+
+    /// Moves the encoder by `travel` counts.
+    fn integrate(&mut self, travel: f64) {
+        let travel = self.residual + travel;
+        // Don't let rounding errors in the step size lose whole counts.
+        let whole = if (travel - travel.round()).abs() < 1e-9 {
+            travel.round()
+        } else {
+            travel.trunc()
+        };
+        self.residual = travel - whole;
+        let old = self.count;
+        self.count = old.wrapping_add(whole as i64 as u32);
+        if whole < 0.0 && self.count > old {
+            self.encoder_status |= ENCODER_UNDERFLOW;
+        }
+        if whole > 0.0 && self.count < old {
+            self.encoder_status |= ENCODER_OVERFLOW;
+        }
+        if self.speed < 0.0 {
+            self.encoder_status |= ENCODER_BACKWARD;
+        } else if self.speed > 0.0 {
+            self.encoder_status &= !ENCODER_BACKWARD;
+        }
+    }
+
+    /// Reads the encoder register, clearing the sticky wrap-around flags.
+    fn read_encoder(&mut self) -> Vec<u8> {
+        let status = self.encoder_status;
+        self.encoder_status &= ENCODER_BACKWARD;
+        [&split_u32_u8(self.count)[..], &[status]].concat()
+    }
+
+    fn read_speed(&self) -> Vec<u8> {
+        let speed = self.speed.round() as i32;
+        [&split_i32_u8(speed)[..], &[(speed < 0) as u8]].concat()
+    }
+
+    fn current(&self) -> f64 {
+        self.duty.abs() / DUTY_MAX * FULL_DUTY_CURRENT
+    }
+}
+
+/// Reads a big-endian `u32` starting at `data[i]`.
+fn u32_at(data: &[u8], i: usize) -> u32 {
+    join_u8_u32(data[i], data[i + 1], data[i + 2], data[i + 3])
+}
+
+fn i32_at(data: &[u8], i: usize) -> i32 {
+    u32_at(data, i) as i32
+}
+
+fn u16_at(data: &[u8], i: usize) -> u16 {
+    join_u8(data[i], data[i + 1])
+}
+
+fn i16_at(data: &[u8], i: usize) -> i16 {
+    u16_at(data, i) as i16
+}
+
+/// Number of data bytes the host sends with a write command, or `None` for
+/// read commands, which are just an address and a command code.
+fn write_len(command: &Command) -> Option<usize> {
+    use Command::*;
+    Some(match command {
+        M1FORWARD | M1BACKWARD | SETMINMB | SETMAXMB | M2FORWARD | M2BACKWARD | M17BIT | M27BIT
+        | MIXEDFORWARD | MIXEDBACKWARD | MIXEDRIGHT | MIXEDLEFT | MIXEDFB | MIXEDLR | SETMINLB
+        | SETMAXLB | SETM1ENCODERMODE | SETM2ENCODERMODE | SETPWMMODE => 1,
+        M1DUTY | M2DUTY | SETDEADBAND | SETCONFIG => 2,
+        SETPINFUNCTIONS => 3,
+        SETM1ENCCOUNT | SETM2ENCCOUNT | MIXEDDUTY | M1SPEED | M2SPEED | SETMAINVOLTAGES
+        | SETLOGICVOLTAGES | SETM1DEFAULTACCEL | SETM2DEFAULTACCEL | WRITENVM => 4,
+        M1DUTYACCEL | M2DUTYACCEL => 6,
+        MIXEDSPEED | M1SPEEDACCEL | M2SPEEDACCEL | SETM1MAXCURRENT | SETM2MAXCURRENT => 8,
+        M1SPEEDDIST | M2SPEEDDIST => 9,
+        MIXEDSPEEDACCEL | MIXEDDUTYACCEL => 12,
+        M1SPEEDACCELDIST | M2SPEEDACCELDIST => 13,
+        SETM1PID | SETM2PID | MIXEDSPEED2ACCEL => 16,
+        MIXEDSPEEDDIST | M1SPEEDACCELDECCELPOS | M2SPEEDACCELDECCELPOS => 17,
+        MIXEDSPEEDACCELDIST => 21,
+        MIXEDSPEED2ACCELDIST => 25,
+        SETM1POSPID | SETM2POSPID => 28,
+        MIXEDSPEEDACCELDECCELPOS => 33,
+        RESETENC | RESTOREDEFAULTS | READNVM | FLAGBOOTLOADER => 0,
+        GETM1ENC
+        | GETM2ENC
+        | GETM1SPEED
+        | GETM2SPEED
+        | GETVERSION
+        | GETMBATT
+        | GETLBATT
+        | GETM1ISPEED
+        | GETM2ISPEED
+        | GETBUFFERS
+        | GETPWMS
+        | GETCURRENTS
+        | READM1PID
+        | READM2PID
+        | GETMINMAXMAINVOLTAGES
+        | GETMINMAXLOGICVOLTAGES
+        | READM1POSPID
+        | READM2POSPID
+        | GETPINFUNCTIONS
+        | GETDEADBAND
+        | GETENCODERS
+        | GETISPEEDS
+        | GETTEMP
+        | GETTEMP2
+        | GETERROR
+        | GETENCODERMODE
+        | GETCONFIG
+        | GETM1MAXCURRENT
+        | GETM2MAXCURRENT
+        | GETPWMMODE => return None,
+    })
+}
+
+struct Device {
+    address: u8,
+    version: String,
+    settings: Settings,
+    nvm: Settings,
+    channels: [Channel; 2],
+    /// Mixed mode drive and turn, from -1.0 to 1.0.
+    mixed: (f64, f64),
+    main_battery: u16,
+    logic_battery: u16,
+    temperatures: (u16, u16),
+    injected_errors: StatusFlags,
+    /// Number of upcoming responses to leave out.
+    drop_responses: u32,
+    /// Number of upcoming responses to damage.
+    corrupt_responses: u32,
+    /// Bytes received from the host that don't form a full packet yet.
+    rx: Vec<u8>,
+    /// Bytes waiting to be sent to the host.
+    tx: VecDeque<u8>,
+    /// When the physics model was last advanced, if it follows the wall clock.
+    clock: Option<Instant>,
+}
+
+impl Device {
+    fn new(address: u8) -> Self {
+        Device {
+            address,
+            version: "USB Roboclaw 2x15a v4.1.34\n".to_owned(),
+            settings: Settings::default(),
+            nvm: Settings::default(),
+            channels: Default::default(),
+            mixed: (0.0, 0.0),
+            main_battery: 120,
+            logic_battery: 120,
+            temperatures: (250, 250),
+            injected_errors: StatusFlags::NORMAL,
+            drop_responses: 0,
+            corrupt_responses: 0,
+            rx: Vec::new(),
+            tx: VecDeque::new(),
+            clock: None,
+        }
+    }
+
+    fn advance(&mut self, dt: f64) {
+        let steps = (dt / STEP).floor();
+        let rest = dt - steps * STEP;
+        let steps = (0..steps as u64).map(|_| STEP);
+        for step in steps.chain((rest > 0.0).then_some(rest)) {
+            for (channel, pid) in self.channels.iter_mut().zip(self.settings.velocity_pid) {
+                channel.step(pid.qpps.max(1) as f64, step);
+            }
+        }
+    }
+
+    fn sync_clock(&mut self) {
+        if let Some(last) = self.clock {
+            let now = Instant::now();
+            self.advance((now - last).as_secs_f64());
+            self.clock = Some(now);
+        }
+    }
+
+    fn receive(&mut self, bytes: &[u8]) {
+        self.sync_clock();
+        self.rx.extend_from_slice(bytes);
+        loop {
+            match self.rx.first() {
+                None => break,
+                Some(0x80..=0x87) => {}
+                // Like the controller, drop the whole packet rather than
+                // hunting for the start of the next one.
+                Some(_) => {
+                    self.rx.clear();
+                    break;
+                }
+            }
+            let Some(&code) = self.rx.get(1) else { break };
+            let Ok(command) = Command::try_from(code) else {
+                self.rx.clear();
+                break;
+            };
+            match write_len(&command) {
+                None => {
+                    let address = self.rx[0];
+                    self.rx.drain(..2);
+                    if address == self.address {
+                        let data = self.read(command);
+                        let packet = [&[address, code][..], &data[..]].concat();
+                        let crc = crc16::State::<crc16::XMODEM>::calculate(&packet);
+                        self.respond([&data[..], &split_u16_u8(crc)].concat());
+                    }
+                }
+                Some(len) => {
+                    if self.rx.len() < len + 4 {
+                        break;
+                    }
+                    let packet: Vec<u8> = self.rx.drain(..len + 4).collect();
+                    let crc_read = join_u8(packet[len + 2], packet[len + 3]);
+                    let crc_calc = crc16::State::<crc16::XMODEM>::calculate(&packet[..len + 2]);
+                    if crc_read == crc_calc
+                        && packet[0] == self.address
+                        && self.write(command, &packet[2..len + 2])
+                    {
+                        self.respond(vec![0xFF]);
+                    }
+                }
+            }
+        }
+    }
+
+    fn respond(&mut self, mut response: Vec<u8>) {
+        if self.drop_responses > 0 {
+            self.drop_responses -= 1;
+            return;
+        }
+        if self.corrupt_responses > 0 {
+            self.corrupt_responses -= 1;
+            // Garbles the CRC of a read or the acknowledgement of a write.
+            if let Some(last) = response.last_mut() {
+                *last ^= 0xFF;
+            }
+        }
+        self.tx.extend(response);
+    }
+
+    fn status(&self) -> StatusFlags {
+        let mut status = self.injected_errors;
+        let (min_main, max_main) = self.settings.main_voltage_limits;
+        let (min_logic, max_logic) = self.settings.logic_voltage_limits;
+        if self.main_battery > max_main {
+            status |= StatusFlags::MAIN_VOLTAGE_HIGH_ERROR;
+        }
+        if self.main_battery < min_main {
+            status |= StatusFlags::MAIN_VOLTAGE_LOW_WARNING;
+        }
+        if self.logic_battery > max_logic {
+            status |= StatusFlags::LOGIC_VOLTAGE_HIGH_ERROR;
+        }
+        if self.logic_battery < min_logic {
+            status |= StatusFlags::LOGIC_VOLTAGE_LOW_ERROR;
+        }
+        let [m1, m2] = &self.channels;
+        if m1.current() * 100.0 > self.settings.max_current[0].0 as f64 {
+            status |= StatusFlags::M1_OVERCURRENT_WARNING;
+        }
+        if m2.current() * 100.0 > self.settings.max_current[1].0 as f64 {
+            status |= StatusFlags::M2_OVERCURRENT_WARNING;
+        }
+        status
+    }
+
+    fn read(&mut self, command: Command) -> Vec<u8> {
+        use Command::*;
+        let settings = &self.settings;
+        let [m1, m2] = &mut self.channels;
+        match command {
+            GETM1ENC => m1.read_encoder(),
+            GETM2ENC => m2.read_encoder(),
+            GETM1SPEED | GETM1ISPEED => m1.read_speed(),
+            GETM2SPEED | GETM2ISPEED => m2.read_speed(),
+            GETVERSION => [self.version.as_bytes(), &[0]].concat(),
+            GETMBATT => split_u16_u8(self.main_battery).to_vec(),
+            GETLBATT => split_u16_u8(self.logic_battery).to_vec(),
+            GETBUFFERS => vec![m1.buffer_status(), m2.buffer_status()],
+            GETPWMS => [split_i16_u8(m1.duty as i16), split_i16_u8(m2.duty as i16)].concat(),
+            GETCURRENTS => [
+                split_i16_u8((m1.current() * 100.0) as i16),
+                split_i16_u8((m2.current() * 100.0) as i16),
+            ]
+            .concat(),
+            READM1PID | READM2PID => {
+                let pid = settings.velocity_pid[(command == READM2PID) as usize];
+                [pid.p, pid.i, pid.d, pid.qpps]
+                    .iter()
+                    .flat_map(|x| split_u32_u8(*x))
+                    .collect()
+            }
+            GETMINMAXMAINVOLTAGES => {
+                let (min, max) = settings.main_voltage_limits;
+                [split_u16_u8(min), split_u16_u8(max)].concat()
+            }
+            GETMINMAXLOGICVOLTAGES => {
+                let (min, max) = settings.logic_voltage_limits;
+                [split_u16_u8(min), split_u16_u8(max)].concat()
+            }
+            READM1POSPID | READM2POSPID => {
+                let pid = settings.position_pid[(command == READM2POSPID) as usize];
+                [
+                    pid.p,
+                    pid.i,
+                    pid.d,
+                    pid.i_max,
+                    pid.deadzone,
+                    pid.min,
+                    pid.max,
+                ]
+                .iter()
+                .flat_map(|x| split_u32_u8(*x))
+                .collect()
+            }
+            GETPINFUNCTIONS => settings.pin_functions.to_vec(),
+            GETDEADBAND => vec![settings.deadband.0, settings.deadband.1],
+            GETENCODERS => [split_u32_u8(m1.count), split_u32_u8(m2.count)].concat(),
+            GETISPEEDS => [
+                split_i32_u8(m1.speed.round() as i32),
+                split_i32_u8(m2.speed.round() as i32),
+            ]
+            .concat(),
+            GETTEMP => split_u16_u8(self.temperatures.0).to_vec(),
+            GETTEMP2 => split_u16_u8(self.temperatures.1).to_vec(),
+            GETERROR => split_u32_u8(self.status().bits()).to_vec(),
+            GETENCODERMODE => settings.encoder_mode.to_vec(),
+            GETCONFIG => split_u16_u8(settings.config).to_vec(),
+            GETM1MAXCURRENT | GETM2MAXCURRENT => {
+                let (max, min) = settings.max_current[(command == GETM2MAXCURRENT) as usize];
+                [split_u32_u8(max), split_u32_u8(min)].concat()
+            }
+            GETPWMMODE => vec![settings.pwm_mode],
+            _ => unreachable!("not a read command"),
+        }
+    }
+
+    fn set_duty(&mut self, channel: usize, duty: f64) {
+        let accel = self.settings.default_accel[channel] as f64;
+        self.channels[channel].set_drive(Drive::Duty {
+            target: duty.clamp(-DUTY_MAX, DUTY_MAX),
+            accel,
+        });
+    }
+
+    fn set_mixed(&mut self, drive: Option<f64>, turn: Option<f64>) {
+        self.mixed = (drive.unwrap_or(self.mixed.0), turn.unwrap_or(self.mixed.1));
+        let (drive, turn) = self.mixed;
+        self.set_duty(0, (drive + turn) * DUTY_MAX);
+        self.set_duty(1, (drive - turn) * DUTY_MAX);
+    }
+
+    /// Buffers one segment per channel, rejecting both if either is full.
+    fn push(&mut self, segments: [Option<Segment>; 2], flag: u8) -> bool {
+        let full = segments
+            .iter()
+            .zip(&self.channels)
+            .any(|(segment, channel)| segment.is_some() && channel.buffer_full(flag));
+        if full {
+            return false;
+        }
+        for (segment, channel) in segments.into_iter().zip(&mut self.channels) {
+            if let Some(segment) = segment {
+                channel.push(segment, flag);
+            }
+        }
+        true
+    }
+
+    /// Applies a write command, returning whether it was accepted.
+    fn write(&mut self, command: Command, d: &[u8]) -> bool {
+        use Command::*;
+        // Old-style commands take a 0 to 127 speed, 7-bit ones center on 64.
+        let speed = |byte: u8| byte.min(127) as f64 / 127.0;
+        let signed = |byte: u8| ((byte.min(127) as f64 - 64.0) / 63.0).clamp(-1.0, 1.0);
+        let distance = |speed: i32, distance: u32| Segment::Distance {
+            accel: 0,
+            speed,
+            distance,
+        };
+        let speed_accel_distance = |d: &[u8], i: usize| Segment::Distance {
+            accel: u32_at(d, i),
+            speed: i32_at(d, i + 4),
+            distance: u32_at(d, i + 8),
+        };
+        let position = |d: &[u8], i: usize| Segment::Position {
+            accel: u32_at(d, i),
+            speed: u32_at(d, i + 4),
+            deccel: u32_at(d, i + 8),
+            position: i32_at(d, i + 12),
+        };
+        let flag = d.last().copied().unwrap_or(0);
+        match command {
+            M1FORWARD => self.set_duty(0, speed(d[0]) * DUTY_MAX),
+            M1BACKWARD => self.set_duty(0, -speed(d[0]) * DUTY_MAX),
+            M2FORWARD => self.set_duty(1, speed(d[0]) * DUTY_MAX),
+            M2BACKWARD => self.set_duty(1, -speed(d[0]) * DUTY_MAX),
+            M17BIT => self.set_duty(0, signed(d[0]) * DUTY_MAX),
+            M27BIT => self.set_duty(1, signed(d[0]) * DUTY_MAX),
+            MIXEDFORWARD => self.set_mixed(Some(speed(d[0])), None),
+            MIXEDBACKWARD => self.set_mixed(Some(-speed(d[0])), None),
+            MIXEDRIGHT => self.set_mixed(None, Some(speed(d[0]))),
+            MIXEDLEFT => self.set_mixed(None, Some(-speed(d[0]))),
+            MIXEDFB => self.set_mixed(Some(signed(d[0])), None),
+            MIXEDLR => self.set_mixed(None, Some(signed(d[0]))),
+            // Legacy limits: min is (volts - 6) * 5, max is volts * 5.12.
+            SETMINMB => self.settings.main_voltage_limits.0 = 60 + 2 * d[0] as u16,
+            SETMAXMB => self.settings.main_voltage_limits.1 = (d[0] as f64 * 10.0 / 5.12) as u16,
+            SETMINLB => self.settings.logic_voltage_limits.0 = 60 + 2 * d[0] as u16,
+            SETMAXLB => self.settings.logic_voltage_limits.1 = (d[0] as f64 * 10.0 / 5.12) as u16,
+            RESETENC => {
+                for channel in &mut self.channels {
+                    channel.count = 0;
+                    channel.residual = 0.0;
+                }
+            }
+            SETM1ENCCOUNT | SETM2ENCCOUNT => {
+                let channel = &mut self.channels[(command == SETM2ENCCOUNT) as usize];
+                channel.count = u32_at(d, 0);
+                channel.residual = 0.0;
+            }
+            SETM1PID | SETM2PID => {
+                self.settings.velocity_pid[(command == SETM2PID) as usize] = VelocityPid {
+                    d: u32_at(d, 0),
+                    p: u32_at(d, 4),
+                    i: u32_at(d, 8),
+                    qpps: u32_at(d, 12),
+                }
+            }
+            M1DUTY => self.set_duty(0, i16_at(d, 0) as f64),
+            M2DUTY => self.set_duty(1, i16_at(d, 0) as f64),
+            MIXEDDUTY => {
+                self.set_duty(0, i16_at(d, 0) as f64);
+                self.set_duty(1, i16_at(d, 2) as f64);
+            }
+            M1DUTYACCEL | M2DUTYACCEL => self.channels[(command == M2DUTYACCEL) as usize]
+                .set_drive(Drive::Duty {
+                    target: i16_at(d, 0) as f64,
+                    accel: u32_at(d, 2) as f64,
+                }),
+            MIXEDDUTYACCEL => {
+                for (channel, i) in self.channels.iter_mut().zip([0, 6]) {
+                    channel.set_drive(Drive::Duty {
+                        target: i16_at(d, i) as f64,
+                        accel: u32_at(d, i + 2) as f64,
+                    });
+                }
+            }
+            M1SPEED | M2SPEED => {
+                self.channels[(command == M2SPEED) as usize].set_drive(Drive::Speed {
+                    target: i32_at(d, 0) as f64,
+                    accel: 0.0,
+                })
+            }
+            MIXEDSPEED => {
+                for (channel, i) in self.channels.iter_mut().zip([0, 4]) {
+                    channel.set_drive(Drive::Speed {
+                        target: i32_at(d, i) as f64,
+                        accel: 0.0,
+                    });
+                }
+            }
+            M1SPEEDACCEL | M2SPEEDACCEL => self.channels[(command == M2SPEEDACCEL) as usize]
+                .set_drive(Drive::Speed {
+                    target: i32_at(d, 4) as f64,
+                    accel: u32_at(d, 0) as f64,
+                }),
+            MIXEDSPEEDACCEL => {
+                for (channel, i) in self.channels.iter_mut().zip([4, 8]) {
+                    channel.set_drive(Drive::Speed {
+                        target: i32_at(d, i) as f64,
+                        accel: u32_at(d, 0) as f64,
+                    });
+                }
+            }
+            MIXEDSPEED2ACCEL => {
+                for (channel, i) in self.channels.iter_mut().zip([0, 8]) {
+                    channel.set_drive(Drive::Speed {
+                        target: i32_at(d, i + 4) as f64,
+                        accel: u32_at(d, i) as f64,
+                    });
+                }
+            }
+            M1SPEEDDIST => {
+                return self.push([Some(distance(i32_at(d, 0), u32_at(d, 4))), None], flag)
+            }
+            M2SPEEDDIST => {
+                return self.push([None, Some(distance(i32_at(d, 0), u32_at(d, 4)))], flag)
+            }
+            MIXEDSPEEDDIST => {
+                return self.push(
+                    [
+                        Some(distance(i32_at(d, 0), u32_at(d, 4))),
+                        Some(distance(i32_at(d, 8), u32_at(d, 12))),
+                    ],
+                    flag,
+                )
+            }
+            M1SPEEDACCELDIST => return self.push([Some(speed_accel_distance(d, 0)), None], flag),
+            M2SPEEDACCELDIST => return self.push([None, Some(speed_accel_distance(d, 0))], flag),
+            MIXEDSPEEDACCELDIST => {
+                let accel = u32_at(d, 0);
+                let segment = |i| Segment::Distance {
+                    accel,
+                    speed: i32_at(d, i),
+                    distance: u32_at(d, i + 4),
+                };
+                return self.push([Some(segment(4)), Some(segment(12))], flag);
+            }
+            MIXEDSPEED2ACCELDIST => {
+                return self.push(
+                    [
+                        Some(speed_accel_distance(d, 0)),
+                        Some(speed_accel_distance(d, 12)),
+                    ],
+                    flag,
+                )
+            }
+            SETMAINVOLTAGES => self.settings.main_voltage_limits = (u16_at(d, 0), u16_at(d, 2)),
+            SETLOGICVOLTAGES => self.settings.logic_voltage_limits = (u16_at(d, 0), u16_at(d, 2)),
+            SETM1POSPID | SETM2POSPID => {
+                self.settings.position_pid[(command == SETM2POSPID) as usize] = PositionPid {
+                    d: u32_at(d, 0),
+                    p: u32_at(d, 4),
+                    i: u32_at(d, 8),
+                    i_max: u32_at(d, 12),
+                    deadzone: u32_at(d, 16),
+                    min: u32_at(d, 20),
+                    max: u32_at(d, 24),
+                }
+            }
+            M1SPEEDACCELDECCELPOS => return self.push([Some(position(d, 0)), None], flag),
+            M2SPEEDACCELDECCELPOS => return self.push([None, Some(position(d, 0))], flag),
+            MIXEDSPEEDACCELDECCELPOS => {
+                return self.push([Some(position(d, 0)), Some(position(d, 16))], flag)
+            }
+            SETM1DEFAULTACCEL => self.settings.default_accel[0] = u32_at(d, 0),
+            SETM2DEFAULTACCEL => self.settings.default_accel[1] = u32_at(d, 0),
+            SETPINFUNCTIONS => self.settings.pin_functions = [d[0], d[1], d[2]],
+            SETDEADBAND => self.settings.deadband = (d[0], d[1]),
+            RESTOREDEFAULTS => self.settings = Settings::default(),
+            SETM1ENCODERMODE => self.settings.encoder_mode[0] = d[0],
+            SETM2ENCODERMODE => self.settings.encoder_mode[1] = d[0],
+            WRITENVM => {
+                if u32_at(d, 0) != NVM_KEY {
+                    return false;
+                }
+                self.nvm = self.settings.clone();
+            }
+            READNVM => self.settings = self.nvm.clone(),
+            SETCONFIG => self.settings.config = u16_at(d, 0),
+            SETM1MAXCURRENT | SETM2MAXCURRENT => {
+                self.settings.max_current[(command == SETM2MAXCURRENT) as usize] =
+                    (u32_at(d, 0), u32_at(d, 4))
+            }
+            SETPWMMODE => self.settings.pwm_mode = d[0],
+            FLAGBOOTLOADER => {}
+            _ => unreachable!("not a write command"),
+        }
+        true
+    }
+}
+
+/// A software Roboclaw that speaks packet serial.
+///
+/// It keeps the same register state as a real controller (encoders, speeds,
+/// PID gains, voltage limits, configuration, error flags, ...) and runs a
+/// simple motor model: each channel's speed follows its duty cycle, with the
+/// velocity PID's QPPS as the speed at full duty, and the encoders integrate
+/// that speed. Packets with a bad CRC or for another address are ignored, as
+/// the hardware does.
+///
+/// The simulator is itself a [`Transport`], so the quickest way to use it is
+/// to hand a clone to [`Roboclaw::new`](crate::Roboclaw::new) and keep the
+/// other for inspecting and driving the model:
+///
+/// ```
+/// use roboclaw::{Roboclaw, Simulator};
+/// use std::time::Duration;
+///
+/// let sim = Simulator::new();
+/// let mut roboclaw = Roboclaw::new(sim.clone());
+/// roboclaw.speed_m1_m2(1000, -500).unwrap();
+/// sim.advance(Duration::from_secs(1));
+/// assert_eq!(roboclaw.read_encoders().unwrap(), (1000, (-500i32) as u32));
+/// ```
+///
+/// Alternatively [`Simulator::serve`] answers packets arriving on any other
+/// transport, such as one end of a [`Loopback`](crate::Loopback) pair or a
+/// pseudo-terminal.
+///
+/// Time only passes when [`Simulator::advance`] is called, unless the
+/// simulator is switched to [`Simulator::realtime`].
+#[derive(Clone)]
+pub struct Simulator {
+    device: Arc<Mutex<Device>>,
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Simulator {
+    /// Creates a simulated controller at [`DEFAULT_ADDRESS`].
+    pub fn new() -> Self {
+        Self::with_address(DEFAULT_ADDRESS)
+    }
+
+    /// Creates a simulated controller answering to `address`.
+    pub fn with_address(address: u8) -> Self {
+        Simulator {
+            device: Arc::new(Mutex::new(Device::new(address))),
+        }
+    }
+
+    /// Makes the motor model follow the wall clock instead of
+    /// [`Simulator::advance`].
+    pub fn realtime(self) -> Self {
+        self.device().clock = Some(Instant::now());
+        self
+    }
+
+    fn device(&self) -> MutexGuard<'_, Device> {
+        let mut device = self.device.lock().unwrap_or_else(PoisonError::into_inner);
+        device.sync_clock();
+        device
+    }
+
+    /// Runs the motor model forward by `dt`.
+    pub fn advance(&self, dt: Duration) {
+        self.device().advance(dt.as_secs_f64());
+    }
+
+    /// Raw encoder counts, as returned by `read_encoders`.
+    pub fn encoders(&self) -> (u32, u32) {
+        let device = self.device();
+        (device.channels[0].count, device.channels[1].count)
+    }
+
+    /// Current speeds in encoder counts per second.
+    pub fn speeds(&self) -> (i32, i32) {
+        let device = self.device();
+        (
+            device.channels[0].speed.round() as i32,
+            device.channels[1].speed.round() as i32,
+        )
+    }
+
+    pub fn set_main_battery_voltage(&self, volts: f32) {
+        self.device().main_battery = (volts * 10.0).round() as u16;
+    }
+
+    pub fn set_logic_battery_voltage(&self, volts: f32) {
+        self.device().logic_battery = (volts * 10.0).round() as u16;
+    }
+
+    /// Sets the readings of the first and second temperature sensors, in
+    /// degrees Celsius.
+    pub fn set_temperatures(&self, temperature: f32, temperature2: f32) {
+        self.device().temperatures = (
+            (temperature * 10.0).round() as u16,
+            (temperature2 * 10.0).round() as u16,
+        );
+    }
+
+    /// Sets the string returned by the version command, e.g.
+    /// `"USB Roboclaw 2x60a v4.1.34\n"`.
+    pub fn set_version(&self, version: &str) {
+        self.device().version = version.to_owned();
+    }
+
+    /// Raises error and warning flags on top of the ones the model derives
+    /// from voltages and currents.
+    pub fn inject_errors(&self, flags: StatusFlags) {
+        self.device().injected_errors = flags;
+    }
+
+    /// Leaves out the next `count` responses, as if they were lost on the
+    /// line. The commands themselves still take effect.
+    pub fn drop_responses(&self, count: u32) {
+        self.device().drop_responses = count;
+    }
+
+    /// Damages the next `count` responses, as line noise would.
+    pub fn corrupt_responses(&self, count: u32) {
+        self.device().corrupt_responses = count;
+    }
+
+    /// Answers packets arriving on `transport` until it fails with anything
+    /// other than a timeout.
+    ///
+    /// This blocks, so it's usually run on its own thread.
+    pub fn serve<T: Transport>(&self, mut transport: T) -> io::Result<()> {
+        let mut byte = [0];
+        loop {
+            match transport.read_exact(&mut byte) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => continue,
+                Err(e) => return Err(e),
+            }
+            let response: Vec<u8> = {
+                let mut device = self.device();
+                device.receive(&byte);
+                device.tx.drain(..).collect()
+            };
+            if !response.is_empty() {
+                transport.write_all(&response)?;
+            }
+        }
+    }
+}
+
+impl Transport for Simulator {
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        let mut device = self.device();
+        if device.tx.len() < buf.len() {
+            device.tx.clear();
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "simulator did not respond",
+            ));
+        }
+        let len = buf.len();
+        for (dst, src) in buf.iter_mut().zip(device.tx.drain(..len)) {
+            *dst = src;
+        }
+        Ok(())
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.device().receive(buf);
+        Ok(())
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.device().tx.clear();
+        Ok(())
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    fn set_timeout(&mut self, _timeout: Duration) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,169 @@
+use crate::{Buffer, BufferStatus, Motor, Roboclaw, RoboclawError, Transport};
+use std::thread;
+use std::time::Duration;
+
+/// Motion commands a channel can buffer. The one running is counted as well,
+/// which errs on the side of leaving a slot free.
+const BUFFER_DEPTH: usize = 32;
+
+/// One move of a single channel.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Segment {
+    /// Runs `distance` counts at `speed` counts per second, ramping at
+    /// `accel` counts per second squared.
+    Distance {
+        accel: u32,
+        speed: i32,
+        distance: u32,
+    },
+    /// Moves to the encoder count `position`, ramping up at `accel` and down
+    /// at `deccel`.
+    Position {
+        accel: u32,
+        speed: i32,
+        deccel: u32,
+        position: u32,
+    },
+}
+
+/// How far a [`Trajectory`] has got. Each array holds M1 then M2.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Progress {
+    /// Segments in the trajectory.
+    pub total: [usize; 2],
+    /// Segments handed to the controller.
+    pub sent: [usize; 2],
+    /// Segments the controller has finished.
+    pub completed: [usize; 2],
+}
+
+impl Progress {
+    /// Whether the controller has finished every segment.
+    pub fn is_complete(&self) -> bool {
+        self.completed == self.total
+    }
+}
+
+/// Streams a list of segments per channel through the controller's motion
+/// buffers.
+///
+/// The controller times the moves itself, so the host only has to keep the
+/// buffers topped up; polling every few hundred milliseconds is plenty for
+/// most paths. Segments are queued behind whatever the channels are already
+/// doing, and progress is worked out from the buffer depths, so nothing
+/// else should queue motion commands while a trajectory is running.
+///
+/// Like every queued motion command, segments are sent without retries. If
+/// sending one fails the controller may or may not have taken it, so stop
+/// the motors rather than polling again.
+///
+/// ```no_run
+/// # use roboclaw::{Motor, Roboclaw, Segment, Trajectory};
+/// # use std::time::Duration;
+/// # fn example(roboclaw: &mut Roboclaw) -> Result<(), roboclaw::RoboclawError> {
+/// let mut trajectory = Trajectory::new();
+/// for _ in 0..100 {
+///     trajectory.push(Motor::M1, Segment::Distance { accel: 2000, speed: 1000, distance: 500 });
+///     trajectory.push(Motor::M1, Segment::Distance { accel: 2000, speed: -1000, distance: 500 });
+/// }
+/// trajectory.run(roboclaw, Duration::from_millis(200), |progress| {
+///     println!("{} of {} done", progress.completed[0], progress.total[0]);
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Trajectory {
+    segments: [Vec<Segment>; 2],
+    progress: Progress,
+}
+
+impl Trajectory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a segment to `motor`'s part of the trajectory.
+    pub fn push(&mut self, motor: Motor, segment: Segment) -> &mut Self {
+        self.segments[motor as usize].push(segment);
+        self.progress.total[motor as usize] += 1;
+        self
+    }
+
+    pub fn progress(&self) -> Progress {
+        self.progress
+    }
+
+    /// Checks how far the controller has got and sends as many of the
+    /// remaining segments as its buffers have room for.
+    pub fn poll<T: Transport>(
+        &mut self,
+        roboclaw: &mut Roboclaw<T>,
+    ) -> Result<Progress, RoboclawError> {
+        let (m1, m2) = roboclaw.read_buffers()?;
+        for (motor, status) in Motor::ALL.into_iter().zip([m1, m2]) {
+            let i = motor as usize;
+            let progress = &mut self.progress;
+            // Segments waiting in the buffer plus the one running.
+            let pending = match status {
+                BufferStatus::Empty => 0,
+                BufferStatus::LastCommandExecuting => 1,
+                BufferStatus::NotEmpty(queued) => queued as usize + 1,
+            };
+            let done = progress.sent[i].saturating_sub(pending);
+            progress.completed[i] = progress.completed[i].max(done);
+            let free = BUFFER_DEPTH.saturating_sub(pending);
+            let end = (progress.sent[i] + free).min(self.segments[i].len());
+            for segment in &self.segments[i][progress.sent[i]..end] {
+                send(roboclaw, motor, *segment)?;
+                progress.sent[i] += 1;
+            }
+        }
+        Ok(self.progress)
+    }
+
+    /// Polls every `interval` until the controller has run the whole
+    /// trajectory, passing the progress to `on_progress` each time.
+    pub fn run<T: Transport>(
+        &mut self,
+        roboclaw: &mut Roboclaw<T>,
+        interval: Duration,
+        mut on_progress: impl FnMut(Progress),
+    ) -> Result<(), RoboclawError> {
+        loop {
+            let progress = self.poll(roboclaw)?;
+            on_progress(progress);
+            if progress.is_complete() {
+                return Ok(());
+            }
+            thread::sleep(interval);
+        }
+    }
+}
+
+fn send<T: Transport>(
+    roboclaw: &mut Roboclaw<T>,
+    motor: Motor,
+    segment: Segment,
+) -> Result<(), RoboclawError> {
+    match segment {
+        Segment::Distance {
+            accel,
+            speed,
+            distance,
+        } => roboclaw.speed_accel_distance(motor, accel, speed, distance, Buffer::Queue),
+        Segment::Position {
+            accel,
+            speed,
+            deccel,
+            position,
+        } => roboclaw.speed_accel_deccel_position(
+            motor,
+            accel,
+            speed,
+            deccel,
+            position,
+            Buffer::Queue,
+        ),
+    }
+}
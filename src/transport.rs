@@ -0,0 +1,207 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// A byte stream the packet serial protocol can run over.
+///
+/// Implemented for serial ports, TCP streams (e.g. a TCP-to-serial bridge)
+/// and the in-memory [`Loopback`].
+pub trait Transport {
+    /// Reads exactly `buf.len()` bytes, failing with
+    /// [`io::ErrorKind::TimedOut`] if they don't arrive within the timeout.
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()>;
+
+    /// Writes the whole buffer.
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()>;
+
+    /// Discards any data received but not yet read and any data written but
+    /// not yet transmitted.
+    fn clear(&mut self) -> io::Result<()>;
+
+    /// How long reads wait for data before timing out.
+    fn timeout(&self) -> Duration;
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()>;
+}
+
+impl Transport for Box<dyn serialport::SerialPort> {
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        Read::read_exact(self, buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        Write::write_all(self, buf)
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        serialport::SerialPort::clear(self.as_ref(), serialport::ClearBuffer::All)
+            .map_err(io::Error::from)
+    }
+
+    fn timeout(&self) -> Duration {
+        serialport::SerialPort::timeout(self.as_ref())
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        serialport::SerialPort::set_timeout(self.as_mut(), timeout).map_err(io::Error::from)
+    }
+}
+
+impl Transport for TcpStream {
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        Read::read_exact(self, buf).map_err(|e| match e.kind() {
+            // Sockets report an expired read timeout as `WouldBlock` on Unix.
+            io::ErrorKind::WouldBlock => io::Error::new(io::ErrorKind::TimedOut, e),
+            _ => e,
+        })
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        Write::write_all(self, buf)
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        // There is no way to drop data that's already in flight, so just
+        // drain whatever the socket has buffered so far.
+        self.set_nonblocking(true)?;
+        let mut buf = [0; 256];
+        let drained = loop {
+            match Read::read(self, &mut buf) {
+                Ok(0) => break Ok(()),
+                Ok(_) => continue,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+        self.set_nonblocking(false)?;
+        drained
+    }
+
+    fn timeout(&self) -> Duration {
+        self.read_timeout().ok().flatten().unwrap_or(Duration::MAX)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.set_read_timeout(Some(timeout))?;
+        self.set_write_timeout(Some(timeout))
+    }
+}
+
+#[derive(Default)]
+struct Pipe {
+    buf: Mutex<VecDeque<u8>>,
+    ready: Condvar,
+}
+
+/// One end of an in-memory, full-duplex byte channel.
+///
+/// Bytes written to one end of a [`Loopback::pair`] are read from the other,
+/// like a null-modem cable. Useful for tests and for running the device side
+/// of the protocol in another thread.
+pub struct Loopback {
+    rx: Arc<Pipe>,
+    tx: Arc<Pipe>,
+    timeout: Duration,
+}
+
+impl Loopback {
+    /// Read timeout of newly created ends.
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
+
+    /// Creates two connected ends.
+    pub fn pair() -> (Loopback, Loopback) {
+        let a = Arc::new(Pipe::default());
+        let b = Arc::new(Pipe::default());
+        (
+            Loopback {
+                rx: Arc::clone(&a),
+                tx: Arc::clone(&b),
+                timeout: Self::DEFAULT_TIMEOUT,
+            },
+            Loopback {
+                rx: b,
+                tx: a,
+                timeout: Self::DEFAULT_TIMEOUT,
+            },
+        )
+    }
+
+    /// Number of bytes that can be read without blocking.
+    pub fn available(&self) -> usize {
+        self.rx
+            .buf
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+}
+
+impl Drop for Loopback {
+    fn drop(&mut self) {
+        // Wake up a reader blocked on the other end so it notices.
+        self.tx.ready.notify_all();
+    }
+}
+
+impl Transport for Loopback {
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        let deadline = Instant::now() + self.timeout;
+        let mut pending = self.rx.buf.lock().unwrap_or_else(PoisonError::into_inner);
+        while pending.len() < buf.len() {
+            if Arc::strong_count(&self.rx) == 1 {
+                return Err(io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    "other end of the loopback was dropped",
+                ));
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "loopback read timed out",
+                ));
+            }
+            pending = self
+                .rx
+                .ready
+                .wait_timeout(pending, deadline - now)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+        let len = buf.len();
+        for (dst, src) in buf.iter_mut().zip(pending.drain(..len)) {
+            *dst = src;
+        }
+        Ok(())
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.tx
+            .buf
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .extend(buf);
+        self.tx.ready.notify_all();
+        Ok(())
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.rx
+            .buf
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+        Ok(())
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+use std::fmt;
+
+/// Board and firmware of a controller, as reported by its version string.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Version {
+    /// The string sent by the controller without its trailing newline, e.g.
+    /// `"USB Roboclaw 2x15a v4.1.34"`.
+    pub text: String,
+    /// Board, if the string names a known layout.
+    pub model: Option<Model>,
+    /// Firmware version, if the string contains one.
+    pub firmware: Option<Firmware>,
+}
+
+impl Version {
+    /// Picks the model and firmware version out of a version string. Parts
+    /// that can't be recognised are left as `None`.
+    pub fn parse(text: &str) -> Self {
+        let words = || text.split_whitespace().map(str::to_ascii_lowercase);
+        let mut model = words().find_map(|word| Model::parse(&word));
+        if let Some(model) = &mut model {
+            // Some strings write the HV suffix as a word of its own.
+            model.high_voltage |= words().any(|word| word == "hv");
+        }
+        Version {
+            text: text.trim_end().to_owned(),
+            model,
+            firmware: words().find_map(|word| Firmware::parse(&word)),
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// Channel layout and current rating of a board, e.g. 2x15A or 2x60AHV.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Model {
+    pub channels: u8,
+    /// Continuous current rating per channel, in amps.
+    pub amps: u16,
+    /// Whether it's an HV board, taking a main battery above 34V.
+    pub high_voltage: bool,
+}
+
+impl Model {
+    /// Parses a lowercase word like `2x15a` or `2x60ahv`.
+    fn parse(word: &str) -> Option<Self> {
+        let (word, high_voltage) = match word.strip_suffix("hv") {
+            Some(word) => (word, true),
+            None => (word, false),
+        };
+        let (channels, amps) = word.strip_suffix('a')?.split_once('x')?;
+        Some(Model {
+            channels: channels.parse().ok()?,
+            amps: amps.parse().ok()?,
+            high_voltage,
+        })
+    }
+
+    /// Whether the board has the second temperature sensor read by
+    /// [`Roboclaw::read_temperature2`](crate::Roboclaw::read_temperature2).
+    /// Only the 2x60A and bigger boards do.
+    pub fn has_second_temperature_sensor(&self) -> bool {
+        self.amps >= 60
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}A", self.channels, self.amps)?;
+        if self.high_voltage {
+            write!(f, "HV")?;
+        }
+        Ok(())
+    }
+}
+
+/// Firmware version, ordered so that newer versions compare greater.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Firmware {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+}
+
+impl Firmware {
+    /// Parses a lowercase word like `v4.1.34`.
+    fn parse(word: &str) -> Option<Self> {
+        let mut parts = word.strip_prefix('v')?.split('.');
+        let mut next = || parts.next()?.parse().ok();
+        let firmware = Firmware {
+            major: next()?,
+            minor: next()?,
+            patch: next()?,
+        };
+        parts.next().is_none().then_some(firmware)
+    }
+}
+
+impl fmt::Display for Firmware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
@@ -0,0 +1,44 @@
+// Each test crate uses a different subset of these.
+#![allow(dead_code)]
+
+use roboclaw::{Loopback, Transport};
+use std::thread::{self, JoinHandle};
+
+/// Appends the CRC the controller expects after `packet`.
+pub fn with_crc(packet: &[u8]) -> Vec<u8> {
+    let crc = crc16::State::<crc16::XMODEM>::calculate(packet);
+    [packet, &crc.to_be_bytes()].concat()
+}
+
+/// Waits for a request, answers it with `data` (followed by a CRC if it
+/// isn't just an ack) and returns the request.
+fn answer(device: &mut Loopback, data: &[u8]) -> Vec<u8> {
+    let mut first = [0];
+    device.read_exact(&mut first).unwrap();
+    let mut request = vec![0; device.available()];
+    device.read_exact(&mut request).unwrap();
+    request.insert(0, first[0]);
+    if data == [0xFF] {
+        device.write_all(data).unwrap();
+    } else {
+        let response = with_crc(&[&request[..], data].concat());
+        device.write_all(&response[request.len()..]).unwrap();
+    }
+    request
+}
+
+/// Plays the device side of one packet exchange, returning the request.
+pub fn respond_once(mut device: Loopback, data: Vec<u8>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || answer(&mut device, &data))
+}
+
+/// Plays the device side of one exchange per entry of `responses`,
+/// returning the requests.
+pub fn respond(mut device: Loopback, responses: Vec<Vec<u8>>) -> JoinHandle<Vec<Vec<u8>>> {
+    thread::spawn(move || {
+        responses
+            .iter()
+            .map(|data| answer(&mut device, data))
+            .collect()
+    })
+}
@@ -0,0 +1,62 @@
+use roboclaw::{
+    BatteryMode, BaudRate, Config, ControlMode, RcOptions, Roboclaw, RoboclawError, Simulator,
+};
+
+#[test]
+fn decodes_overlapping_fields_by_mode() {
+    let serial = Config::from_bits(0x2167);
+    assert_eq!(
+        serial.control_mode,
+        ControlMode::PacketSerial {
+            baud_rate: BaudRate::Baud38400,
+            address: 0x81
+        }
+    );
+    assert_eq!(serial.battery_mode, BatteryMode::Auto);
+    assert!(serial.swap_encoders);
+
+    // The same low bits mean mixing and exponential in RC mode.
+    let rc = Config::from_bits(0x0164);
+    assert_eq!(
+        rc.control_mode,
+        ControlMode::Rc(RcOptions {
+            mixing: true,
+            exponential: true,
+            mcu: false,
+            flip_switch: true
+        })
+    );
+    assert_eq!(rc.bits(), Some(0x0164));
+}
+
+#[test]
+fn set_config_round_trip() {
+    let mut roboclaw = Roboclaw::new(Simulator::new());
+
+    let mut config = roboclaw.get_config().unwrap();
+    assert_eq!(
+        config.control_mode,
+        ControlMode::PacketSerial {
+            baud_rate: BaudRate::Baud38400,
+            address: 0x80
+        }
+    );
+
+    config.control_mode = ControlMode::PacketSerial {
+        baud_rate: BaudRate::Baud115200,
+        address: 0x83,
+    };
+    config.battery_mode = BatteryMode::ThreeCell;
+    config.multi_unit_mode = true;
+    roboclaw.set_config(&config).unwrap();
+    assert_eq!(roboclaw.get_config().unwrap(), config);
+
+    config.control_mode = ControlMode::PacketSerial {
+        baud_rate: BaudRate::Baud115200,
+        address: 0x90,
+    };
+    assert!(matches!(
+        roboclaw.set_config(&config),
+        Err(RoboclawError::InvalidArgument { .. })
+    ));
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use common::respond_once;
+use roboclaw::{Loopback, Motor, PositionPid, Roboclaw, Simulator, VelocityPid};
+
+#[test]
+fn velocity_pid_wire_format() {
+    let (host, device) = Loopback::pair();
+    let mut roboclaw = Roboclaw::new(host);
+
+    let request = respond_once(device, vec![0xFF]);
+    roboclaw
+        .set_velocity_pid(
+            Motor::M2,
+            VelocityPid {
+                kp: 1.5,
+                ki: 0.25,
+                kd: 0.0625,
+                qpps: 44000,
+            },
+        )
+        .unwrap();
+    let request = request.join().unwrap();
+    assert_eq!(
+        request[..18],
+        [
+            0x80, 29, // address and command
+            0x00, 0x00, 0x10, 0x00, // kd
+            0x00, 0x01, 0x80, 0x00, // kp
+            0x00, 0x00, 0x40, 0x00, // ki
+            0x00, 0x00, 0xAB, 0xE0, // qpps
+        ]
+    );
+    assert_eq!(request.len(), 20);
+}
+
+#[test]
+fn read_velocity_pid_decodes_16_16() {
+    let (host, device) = Loopback::pair();
+    let mut roboclaw = Roboclaw::new(host);
+
+    let request = respond_once(
+        device,
+        vec![
+            0x00, 0x02, 0x40, 0x00, // kp
+            0x00, 0x00, 0x00, 0x01, // ki
+            0x00, 0x00, 0x00, 0x00, // kd
+            0x00, 0x00, 0x27, 0x10, // qpps
+        ],
+    );
+    let pid = roboclaw.read_velocity_pid(Motor::M1).unwrap();
+    assert_eq!(request.join().unwrap(), [0x80, 55]);
+    assert_eq!(
+        pid,
+        VelocityPid {
+            kp: 2.25,
+            ki: 1.0 / 65536.0,
+            kd: 0.0,
+            qpps: 10000,
+        }
+    );
+}
+
+#[test]
+fn velocity_pid_round_trip() {
+    let mut roboclaw = Roboclaw::new(Simulator::new());
+    let m1 = VelocityPid {
+        kp: 3.0,
+        ki: 0.5,
+        kd: 0.125,
+        qpps: 12000,
+    };
+    let m2 = VelocityPid {
+        kp: 7.75,
+        ki: 0.0,
+        kd: 1.0,
+        qpps: 3000,
+    };
+
+    roboclaw.set_velocity_pid(Motor::M1, m1).unwrap();
+    roboclaw.set_velocity_pid(Motor::M2, m2).unwrap();
+    assert_eq!(roboclaw.read_velocity_pid(Motor::M1).unwrap(), m1);
+    assert_eq!(roboclaw.read_velocity_pid(Motor::M2).unwrap(), m2);
+}
+
+#[test]
+fn position_pid_round_trip() {
+    let mut roboclaw = Roboclaw::new(Simulator::new());
+    let pid = PositionPid {
+        kp: 120.5,
+        ki: 0.75,
+        kd: 800.0,
+        ki_max: 2000,
+        deadzone: 5,
+        min: -4000,
+        max: 4000,
+    };
+
+    roboclaw.set_position_pid(Motor::M2, pid).unwrap();
+    assert_eq!(roboclaw.read_position_pid(Motor::M2).unwrap(), pid);
+    assert_eq!(roboclaw.read_position_pid(Motor::M1).unwrap().kp, 0.0);
+}
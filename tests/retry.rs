@@ -0,0 +1,114 @@
+mod common;
+
+use roboclaw::{Loopback, RetryPolicy, RetryStats, Roboclaw, RoboclawError, Simulator, Transport};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn retries_transient_errors() {
+    let sim = Simulator::new();
+    let mut roboclaw = Roboclaw::new(sim.clone());
+
+    sim.corrupt_responses(1);
+    sim.drop_responses(1);
+    assert_eq!(roboclaw.read_main_battery_voltage().unwrap(), 12.0);
+    sim.corrupt_responses(2);
+    roboclaw.duty_m1(1000).unwrap();
+
+    assert_eq!(
+        roboclaw.retry_stats(),
+        RetryStats {
+            packets: 2,
+            retries: 4,
+            failures: 0,
+            crc_errors: 1,
+            timeouts: 1,
+            nacks: 2,
+        }
+    );
+}
+
+#[test]
+fn gives_up_after_the_last_attempt() {
+    let sim = Simulator::new();
+    let mut roboclaw = Roboclaw::new(sim.clone()).with_retry_policy(RetryPolicy {
+        attempts: 2,
+        ..RetryPolicy::default()
+    });
+
+    sim.corrupt_responses(2);
+    assert!(matches!(
+        roboclaw.read_logic_battery_voltage(),
+        Err(RoboclawError::Crc { command: 25, .. })
+    ));
+    assert_eq!(roboclaw.retry_stats().retries, 1);
+    assert_eq!(roboclaw.retry_stats().failures, 1);
+
+    roboclaw.reset_retry_stats();
+    roboclaw.set_retry_policy(RetryPolicy::none());
+    sim.drop_responses(1);
+    assert!(matches!(
+        roboclaw.read_encoders(),
+        Err(RoboclawError::Timeout)
+    ));
+    assert_eq!(roboclaw.retry_stats().retries, 0);
+}
+
+#[test]
+fn flushing_skips_stale_replies() {
+    for flush in [true, false] {
+        let (host, mut device) = Loopback::pair();
+        let mut roboclaw = Roboclaw::new(host).with_retry_policy(RetryPolicy {
+            attempts: 1,
+            flush,
+            ..RetryPolicy::default()
+        });
+
+        // Left behind by a read that gave up before the answer came in.
+        device.write_all(&[0x12]).unwrap();
+        let request = common::respond_once(device, vec![0xFF]);
+        let result = roboclaw.duty_m1(1000);
+        request.join().unwrap();
+        if flush {
+            result.unwrap();
+        } else {
+            assert!(matches!(
+                result,
+                Err(RoboclawError::Nack { byte: 0x12, .. })
+            ));
+        }
+    }
+}
+
+#[test]
+fn writes_skip_stale_replies() {
+    let (host, mut device) = Loopback::pair();
+    let mut roboclaw = Roboclaw::new(host);
+
+    // Left behind by a read that gave up before the answer came in.
+    device.write_all(&[0x12]).unwrap();
+    let request = common::respond_once(device, vec![0xFF]);
+    roboclaw.duty_m1(1000).unwrap();
+    request.join().unwrap();
+    assert_eq!(roboclaw.retry_stats().nacks, 0);
+    assert_eq!(roboclaw.retry_stats().retries, 0);
+}
+
+#[test]
+fn backoff_releases_the_port() {
+    let sim = Simulator::new();
+    let mut slow = Roboclaw::new(sim.clone()).with_retry_policy(RetryPolicy {
+        attempts: 2,
+        backoff: Duration::from_millis(500),
+        ..RetryPolicy::default()
+    });
+    let mut other = slow.at_address(0x80);
+
+    sim.drop_responses(1);
+    let retrying = thread::spawn(move || slow.read_main_battery_voltage());
+    thread::sleep(Duration::from_millis(100));
+    let start = Instant::now();
+    assert_eq!(other.read_logic_battery_voltage().unwrap(), 12.0);
+    assert!(start.elapsed() < Duration::from_millis(300));
+    assert_eq!(retrying.join().unwrap().unwrap(), 12.0);
+}
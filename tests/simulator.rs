@@ -0,0 +1,441 @@
+use roboclaw::{
+    Buffer, BufferStatus, Confirm, Direction, Encoder, EncoderMode, Loopback, Motor, PinFunctions,
+    PwmMode, Roboclaw, RoboclawError, S3Mode, Simulator, Speed, StatusFlags, SwitchMode, Transport,
+};
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn speed_moves_encoders() {
+    let sim = Simulator::new();
+    let mut roboclaw = Roboclaw::new(sim.clone());
+
+    roboclaw.speed_m1_m2(2000, -1000).unwrap();
+    sim.advance(Duration::from_millis(500));
+
+    assert_eq!(sim.speeds(), (2000, -1000));
+    assert_eq!(roboclaw.read_encoders().unwrap(), (1000, (-500i32) as u32));
+
+    roboclaw.reset_encoders().unwrap();
+    assert_eq!(roboclaw.read_encoders().unwrap(), (0, 0));
+}
+
+#[test]
+fn presets_and_reads_single_encoders() {
+    let sim = Simulator::new();
+    let mut roboclaw = Roboclaw::new(sim.clone());
+
+    roboclaw.set_encoder(Motor::M1, 10).unwrap();
+    roboclaw.set_encoder(Motor::M2, 5000).unwrap();
+    roboclaw.speed_m1_m2(-100, 0).unwrap();
+    sim.advance(Duration::from_millis(200));
+
+    assert_eq!(
+        roboclaw.read_encoder(Motor::M1).unwrap(),
+        Encoder {
+            count: -10,
+            underflow: true,
+            overflow: false,
+            direction: Direction::Backward
+        }
+    );
+    // The wrap-around flags are cleared by reading.
+    assert!(!roboclaw.read_encoder(Motor::M1).unwrap().underflow);
+    assert_eq!(roboclaw.read_encoder(Motor::M2).unwrap().count, 5000);
+}
+
+#[test]
+fn reads_back_speeds() {
+    let sim = Simulator::new();
+    let mut roboclaw = Roboclaw::new(sim.clone());
+
+    roboclaw.speed_m1_m2(1500, -700).unwrap();
+    sim.advance(Duration::from_millis(10));
+    assert_eq!(
+        roboclaw.read_speed(Motor::M1).unwrap(),
+        Speed {
+            qpps: 1500,
+            direction: Direction::Forward
+        }
+    );
+    assert_eq!(
+        roboclaw.read_instantaneous_speed(Motor::M2).unwrap(),
+        Speed {
+            qpps: -700,
+            direction: Direction::Backward
+        }
+    );
+    assert_eq!(roboclaw.read_instantaneous_speeds().unwrap(), (1500, -700));
+}
+
+#[test]
+fn accelerated_commands_ramp() {
+    let sim = Simulator::new();
+    let mut roboclaw = Roboclaw::new(sim.clone());
+
+    roboclaw.speed_accel(Motor::M1, 1000, 2000).unwrap();
+    roboclaw.duty_accel(Motor::M2, -16384, 16384).unwrap();
+    sim.advance(Duration::from_millis(500));
+    assert_eq!(sim.speeds(), (500, -2500));
+
+    roboclaw.speed_accel_m1_m2_2(4000, -2000, 0, 0).unwrap();
+    sim.advance(Duration::from_millis(500));
+    assert_eq!(sim.speeds(), (-1500, 0));
+}
+
+#[test]
+fn distance_commands_drain_the_buffer() {
+    let sim = Simulator::new();
+    let mut roboclaw = Roboclaw::new(sim.clone());
+
+    roboclaw
+        .speed_distance_m1_m2(1000, 500, 1000, 250, Buffer::Immediate)
+        .unwrap();
+    assert_eq!(
+        roboclaw.read_buffers().unwrap(),
+        (
+            BufferStatus::LastCommandExecuting,
+            BufferStatus::LastCommandExecuting
+        )
+    );
+
+    sim.advance(Duration::from_secs(1));
+    assert_eq!(
+        roboclaw.read_buffers().unwrap(),
+        (BufferStatus::Empty, BufferStatus::Empty)
+    );
+    assert_eq!(sim.speeds(), (0, 0));
+    assert_eq!(roboclaw.read_encoders().unwrap(), (500, 250));
+}
+
+#[test]
+fn distance_moves_stop_on_their_count() {
+    let sim = Simulator::new();
+    let mut roboclaw = Roboclaw::new(sim.clone());
+
+    roboclaw
+        .speed_accel_distance(Motor::M2, 0, 2000, 50, Buffer::Immediate)
+        .unwrap();
+    sim.advance(Duration::from_micros(24950));
+    sim.advance(Duration::from_millis(100));
+    assert_eq!(sim.encoders(), (0, 50));
+    assert_eq!(sim.speeds(), (0, 0));
+
+    // Ramping down at the end of the move doesn't carry it past either.
+    roboclaw
+        .speed_accel_distance(Motor::M2, 10000, -2000, 150, Buffer::Immediate)
+        .unwrap();
+    sim.advance(Duration::from_secs(1));
+    assert_eq!(sim.encoders(), (0, (-100i32) as u32));
+}
+
+#[test]
+fn reads_back_pwms_and_currents() {
+    let sim = Simulator::new();
+    let mut roboclaw = Roboclaw::new(sim.clone());
+
+    roboclaw.duty_m1_m2(16384, -8192).unwrap();
+    sim.advance(Duration::from_millis(1));
+    assert_eq!(roboclaw.read_pwms().unwrap(), (16384, -8192));
+    assert_eq!(roboclaw.read_currents().unwrap(), (1.0, 0.5));
+}
+
+#[test]
+fn position_moves_are_independent() {
+    let sim = Simulator::new();
+    let mut roboclaw = Roboclaw::new(sim.clone());
+
+    roboclaw.speed_m1_m2(0, 200).unwrap();
+    roboclaw
+        .speed_accel_deccel_position(Motor::M1, 4000, 2000, 4000, 1000, Buffer::Immediate)
+        .unwrap();
+    sim.advance(Duration::from_secs(2));
+
+    assert_eq!(sim.speeds(), (0, 200));
+    assert_eq!(roboclaw.read_encoders().unwrap(), (1000, 400));
+    assert_eq!(roboclaw.read_buffers().unwrap().0, BufferStatus::Empty);
+}
+
+#[test]
+fn queued_commands_run_in_order() {
+    let sim = Simulator::new();
+    let mut roboclaw = Roboclaw::new(sim.clone());
+
+    roboclaw
+        .speed_distance(Motor::M1, 1000, 100, Buffer::Queue)
+        .unwrap();
+    roboclaw
+        .speed_distance(Motor::M1, -500, 300, Buffer::Queue)
+        .unwrap();
+    assert_eq!(
+        roboclaw.read_buffers().unwrap().0,
+        BufferStatus::NotEmpty(1)
+    );
+    sim.advance(Duration::from_secs(1));
+    assert_eq!(roboclaw.read_encoders().unwrap().0, (-200i32) as u32);
+
+    // An immediate command replaces whatever is still queued.
+    roboclaw
+        .speed_distance(Motor::M1, 1000, 1000, Buffer::Queue)
+        .unwrap();
+    roboclaw
+        .speed_distance(Motor::M1, 1000, 1000, Buffer::Queue)
+        .unwrap();
+    roboclaw
+        .speed_distance(Motor::M1, -1000, 50, Buffer::Immediate)
+        .unwrap();
+    sim.advance(Duration::from_secs(1));
+    assert_eq!(roboclaw.read_encoders().unwrap().0, (-250i32) as u32);
+}
+
+#[test]
+fn queued_commands_are_not_resent() {
+    let sim = Simulator::new();
+    let mut roboclaw = Roboclaw::new(sim.clone());
+
+    roboclaw
+        .speed_distance(Motor::M1, 1000, 1000, Buffer::Queue)
+        .unwrap();
+    sim.drop_responses(1);
+    assert!(matches!(
+        roboclaw.speed_distance(Motor::M1, 1000, 1000, Buffer::Queue),
+        Err(RoboclawError::Timeout)
+    ));
+    assert_eq!(
+        roboclaw.read_buffers().unwrap().0,
+        BufferStatus::NotEmpty(1)
+    );
+}
+
+#[test]
+fn voltages_and_error_flags() {
+    let sim = Simulator::new();
+    let mut roboclaw = Roboclaw::new(sim.clone());
+
+    sim.set_main_battery_voltage(11.1);
+    sim.set_logic_battery_voltage(5.0);
+    assert_eq!(roboclaw.read_main_battery_voltage().unwrap(), 11.1);
+    assert_eq!(roboclaw.read_logic_battery_voltage().unwrap(), 5.0);
+    assert_eq!(
+        roboclaw.read_error().unwrap(),
+        StatusFlags::LOGIC_VOLTAGE_LOW_ERROR
+    );
+
+    sim.set_main_battery_voltage(40.0);
+    sim.set_logic_battery_voltage(12.0);
+    sim.inject_errors(StatusFlags::E_STOP);
+    assert_eq!(
+        roboclaw.read_error().unwrap(),
+        StatusFlags::E_STOP | StatusFlags::MAIN_VOLTAGE_HIGH_ERROR
+    );
+}
+
+#[test]
+fn sets_voltage_limits() {
+    let sim = Simulator::new();
+    let mut roboclaw = Roboclaw::new(sim.clone());
+
+    roboclaw.set_main_voltages(10.5, 16.8).unwrap();
+    roboclaw.set_logic_voltages(7.0, 12.5).unwrap();
+    assert_eq!(roboclaw.read_min_max_main_voltages().unwrap(), (10.5, 16.8));
+    assert_eq!(roboclaw.read_min_max_logic_voltages().unwrap(), (7.0, 12.5));
+
+    roboclaw.set_min_voltage_main_battery(10.0).unwrap();
+    roboclaw.set_max_voltage_logic_battery(30.0).unwrap();
+    assert_eq!(roboclaw.read_min_max_main_voltages().unwrap(), (10.0, 16.8));
+    assert_eq!(roboclaw.read_min_max_logic_voltages().unwrap(), (7.0, 30.0));
+
+    // Nothing is sent when the limits are out of range.
+    let packets = roboclaw.retry_stats().packets;
+    for result in [
+        roboclaw.set_main_voltages(5.0, 16.8),
+        roboclaw.set_logic_voltages(12.0, 7.0),
+        roboclaw.set_min_voltage_logic_battery(30.0),
+        roboclaw.set_logic_voltages(30.0, 32.0),
+    ] {
+        assert!(matches!(result, Err(RoboclawError::InvalidArgument { .. })));
+    }
+    assert_eq!(roboclaw.retry_stats().packets, packets);
+
+    // Only HV boards take a main battery above 34V.
+    assert!(matches!(
+        roboclaw.set_main_voltages(6.0, 60.0),
+        Err(RoboclawError::InvalidArgument { .. })
+    ));
+    sim.set_version("USB Roboclaw 2x60AHV v4.1.34\n");
+    let mut hv = roboclaw.at_address(0x80);
+    hv.set_main_voltages(6.0, 60.0).unwrap();
+    assert_eq!(hv.read_min_max_main_voltages().unwrap(), (6.0, 60.0));
+    assert_eq!(
+        hv.read_version().unwrap().model.unwrap().to_string(),
+        "2x60AHV"
+    );
+}
+
+#[test]
+fn sets_current_limits() {
+    let mut roboclaw = Roboclaw::new(Simulator::new());
+
+    roboclaw.set_max_current(Motor::M2, 4.25).unwrap();
+    assert_eq!(roboclaw.read_max_current(Motor::M1).unwrap(), 15.0);
+    assert_eq!(roboclaw.read_max_current(Motor::M2).unwrap(), 4.25);
+    assert!(matches!(
+        roboclaw.set_max_current(Motor::M1, -1.0),
+        Err(RoboclawError::InvalidArgument { command: 133, .. })
+    ));
+}
+
+#[test]
+fn sets_encoder_modes() {
+    let mut roboclaw = Roboclaw::new(Simulator::new());
+
+    roboclaw
+        .set_encoder_mode(
+            Motor::M2,
+            EncoderMode::ABSOLUTE | EncoderMode::REVERSE_ENCODER,
+        )
+        .unwrap();
+    assert_eq!(
+        roboclaw.read_encoder_modes().unwrap(),
+        (
+            EncoderMode::empty(),
+            EncoderMode::ABSOLUTE | EncoderMode::REVERSE_ENCODER
+        )
+    );
+    let (m1, m2) = roboclaw.read_encoder_modes().unwrap();
+    assert!(!m1.is_absolute());
+    assert!(m2.is_absolute());
+}
+
+#[test]
+fn sets_pin_functions() {
+    let mut roboclaw = Roboclaw::new(Simulator::new());
+
+    let functions = PinFunctions {
+        s3: S3Mode::EStopLatching,
+        s4: SwitchMode::Home,
+        s5: SwitchMode::LimitBoth,
+    };
+    roboclaw.set_pin_functions(functions).unwrap();
+    assert_eq!(roboclaw.read_pin_functions().unwrap(), functions);
+}
+
+#[test]
+fn sets_deadband_accel_and_pwm_mode() {
+    let sim = Simulator::new();
+    let mut roboclaw = Roboclaw::new(sim.clone());
+
+    roboclaw.set_deadband(1.5, 3.0).unwrap();
+    assert_eq!(roboclaw.read_deadband().unwrap(), (1.5, 3.0));
+    assert!(matches!(
+        roboclaw.set_deadband(0.0, 30.0),
+        Err(RoboclawError::InvalidArgument { .. })
+    ));
+
+    assert_eq!(roboclaw.read_pwm_mode().unwrap(), PwmMode::SignMagnitude);
+    roboclaw.set_pwm_mode(PwmMode::LockedAntiphase).unwrap();
+    assert_eq!(roboclaw.read_pwm_mode().unwrap(), PwmMode::LockedAntiphase);
+
+    // Duty commands ramp at the default acceleration.
+    roboclaw.set_default_accel(Motor::M1, 16384).unwrap();
+    roboclaw.duty(Motor::M1, 32767).unwrap();
+    sim.advance(Duration::from_millis(500));
+    assert_eq!(roboclaw.read_pwms().unwrap().0, 8192);
+}
+
+#[test]
+fn saves_and_restores_settings() {
+    let mut roboclaw = Roboclaw::new(Simulator::new());
+
+    roboclaw.set_max_current(Motor::M1, 4.0).unwrap();
+    roboclaw.write_nvm(Confirm::overwrite_settings()).unwrap();
+    roboclaw.set_max_current(Motor::M1, 8.0).unwrap();
+    roboclaw.read_nvm().unwrap();
+    assert_eq!(roboclaw.read_max_current(Motor::M1).unwrap(), 4.0);
+
+    roboclaw
+        .restore_defaults(Confirm::overwrite_settings())
+        .unwrap();
+    assert_eq!(roboclaw.read_max_current(Motor::M1).unwrap(), 15.0);
+    roboclaw.read_nvm().unwrap();
+    assert_eq!(roboclaw.read_max_current(Motor::M1).unwrap(), 4.0);
+}
+
+#[test]
+fn temperatures_depend_on_the_model() {
+    let sim = Simulator::new();
+    let mut small = Roboclaw::new(sim.clone());
+
+    sim.set_temperatures(31.5, 40.0);
+    assert_eq!(small.read_temperature().unwrap(), 31.5);
+    assert!(matches!(
+        small.read_temperature2(),
+        Err(RoboclawError::Unsupported { command: 83 })
+    ));
+
+    sim.set_version("USB Roboclaw 2x60a v4.1.34\n");
+    let mut big = small.at_address(0x80);
+    assert_eq!(big.read_temperature2().unwrap(), 40.0);
+}
+
+#[test]
+fn ignores_other_addresses() {
+    let sim = Simulator::with_address(0x81);
+    let mut other = Roboclaw::new(sim.clone());
+    let mut roboclaw = other.at_address(0x81);
+
+    assert!(matches!(
+        other.read_main_battery_voltage(),
+        Err(RoboclawError::Timeout)
+    ));
+    assert_eq!(roboclaw.read_main_battery_voltage().unwrap(), 12.0);
+}
+
+#[test]
+fn serves_over_a_loopback() {
+    let (host, device) = Loopback::pair();
+    let sim = Simulator::new();
+    let server = sim.clone();
+    thread::spawn(move || server.serve(device));
+
+    let mut roboclaw = Roboclaw::new(host);
+    roboclaw.speed_m1_m2(1000, 1000).unwrap();
+    sim.advance(Duration::from_secs(1));
+    assert_eq!(roboclaw.read_encoders().unwrap(), (1000, 1000));
+}
+
+#[test]
+fn drops_packets_with_bad_crc() {
+    let (mut host, device) = Loopback::pair();
+    thread::spawn(move || Simulator::new().serve(device));
+
+    // M1 duty 0x1000 with the last CRC byte flipped.
+    let packet = [0x80, 32, 0x10, 0x00];
+    let crc = crc16::State::<crc16::XMODEM>::calculate(&packet);
+    let corrupt = [&packet[..], &[(crc >> 8) as u8, crc as u8 ^ 0xFF]].concat();
+    host.write_all(&corrupt).unwrap();
+    let mut ack = [0];
+    assert!(host.read_exact(&mut ack).is_err());
+
+    let valid = [&packet[..], &[(crc >> 8) as u8, crc as u8]].concat();
+    host.write_all(&valid).unwrap();
+    host.read_exact(&mut ack).unwrap();
+    assert_eq!(ack, [0xFF]);
+}
+
+#[test]
+fn drops_packets_with_a_repeated_address() {
+    let (mut host, device) = Loopback::pair();
+    thread::spawn(move || Simulator::new().serve(device));
+
+    let packet = [0x80, 32, 0x10, 0x00];
+    let crc = crc16::State::<crc16::XMODEM>::calculate(&packet);
+    let valid = [&packet[..], &[(crc >> 8) as u8, crc as u8]].concat();
+    host.write_all(&[&[0x80][..], &valid].concat()).unwrap();
+    let mut ack = [0];
+    assert!(host.read_exact(&mut ack).is_err());
+
+    host.write_all(&valid).unwrap();
+    host.read_exact(&mut ack).unwrap();
+    assert_eq!(ack, [0xFF]);
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use roboclaw::{
+    Loopback, Motor, Progress, Roboclaw, RoboclawError, Segment, Simulator, Trajectory,
+};
+use std::time::Duration;
+
+#[test]
+fn streams_more_segments_than_the_buffer_holds() {
+    let sim = Simulator::new();
+    let mut roboclaw = Roboclaw::new(sim.clone());
+
+    let mut trajectory = Trajectory::new();
+    for _ in 0..50 {
+        trajectory.push(
+            Motor::M1,
+            Segment::Distance {
+                accel: 0,
+                speed: 1000,
+                distance: 100,
+            },
+        );
+    }
+    trajectory.push(
+        Motor::M2,
+        Segment::Position {
+            accel: 0,
+            speed: 1000,
+            deccel: 0,
+            position: 500,
+        },
+    );
+
+    let progress = trajectory.poll(&mut roboclaw).unwrap();
+    assert_eq!(progress.sent, [32, 1]);
+    assert_eq!(progress.completed, [0, 0]);
+
+    sim.advance(Duration::from_millis(1050));
+    let progress = trajectory.poll(&mut roboclaw).unwrap();
+    assert_eq!(progress.completed, [10, 1]);
+    assert_eq!(progress.sent, [42, 1]);
+
+    while !trajectory.poll(&mut roboclaw).unwrap().is_complete() {
+        sim.advance(Duration::from_millis(500));
+    }
+    assert_eq!(
+        trajectory.progress(),
+        Progress {
+            total: [50, 1],
+            sent: [50, 1],
+            completed: [50, 1]
+        }
+    );
+    assert_eq!(roboclaw.read_encoders().unwrap(), (5000, 500));
+}
+
+#[test]
+fn runs_against_the_clock() {
+    let mut roboclaw = Roboclaw::new(Simulator::new().realtime());
+
+    let mut trajectory = Trajectory::new();
+    for speed in [2000, -2000, 2000] {
+        trajectory.push(
+            Motor::M2,
+            Segment::Distance {
+                accel: 0,
+                speed,
+                distance: 50,
+            },
+        );
+    }
+    let mut polls = 0;
+    trajectory
+        .run(&mut roboclaw, Duration::from_millis(10), |_| polls += 1)
+        .unwrap();
+    assert!(polls > 1);
+    // Distance moves end on their count however the steps fall, so this
+    // doesn't depend on timing.
+    assert_eq!(roboclaw.read_encoders().unwrap(), (0, 50));
+}
+
+#[test]
+fn does_not_resend_segments() {
+    let (host, device) = Loopback::pair();
+    let mut roboclaw = Roboclaw::new(host);
+    // Both buffers empty, then a garbled ack for the first segment.
+    let requests = common::respond(device, vec![vec![0x80, 0x80], vec![0x00]]);
+
+    let mut trajectory = Trajectory::new();
+    trajectory.push(
+        Motor::M1,
+        Segment::Distance {
+            accel: 0,
+            speed: 1000,
+            distance: 100,
+        },
+    );
+    assert!(matches!(
+        trajectory.poll(&mut roboclaw),
+        Err(RoboclawError::Nack { command: 44, .. })
+    ));
+    assert_eq!(requests.join().unwrap().len(), 2);
+    assert_eq!(roboclaw.retry_stats().retries, 0);
+    assert_eq!(trajectory.progress().sent, [0, 0]);
+    assert_eq!(roboclaw.retry_policy().attempts, 3);
+}
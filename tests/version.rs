@@ -0,0 +1,46 @@
+use roboclaw::{Firmware, Model, Roboclaw, Simulator, Version};
+
+#[test]
+fn reads_and_parses_the_version() {
+    let sim = Simulator::new();
+    let mut roboclaw = Roboclaw::new(sim.clone());
+
+    let version = roboclaw.read_version().unwrap();
+    assert_eq!(version.text, "USB Roboclaw 2x15a v4.1.34");
+    assert_eq!(
+        version.model,
+        Some(Model {
+            channels: 2,
+            amps: 15,
+            high_voltage: false
+        })
+    );
+    assert_eq!(
+        version.firmware,
+        Some(Firmware {
+            major: 4,
+            minor: 1,
+            patch: 34
+        })
+    );
+    assert_eq!(version.model.unwrap().to_string(), "2x15A");
+}
+
+#[test]
+fn leaves_unknown_parts_out() {
+    let version = Version::parse("RoboClaw 2x60A HV v4.2\n");
+    assert_eq!(version.model.map(|model| model.amps), Some(60));
+    assert_eq!(version.model.map(|model| model.high_voltage), Some(true));
+    assert_eq!(version.firmware, None);
+
+    let version = Version::parse("Solo v4.10.2");
+    assert_eq!(version.model, None);
+    assert!(
+        version.firmware.unwrap()
+            > Firmware {
+                major: 4,
+                minor: 2,
+                patch: 0
+            }
+    );
+}
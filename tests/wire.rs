@@ -0,0 +1,495 @@
+//! Golden byte sequences for every implemented command, checked against an
+//! in-memory port.
+
+mod common;
+
+use common::{respond, with_crc};
+use roboclaw::{
+    BatteryMode, BaudRate, Buffer, BufferStatus, Config, Confirm, ControlMode, Direction, Encoder,
+    EncoderMode, Loopback, Motor, PinFunctions, PositionPid, PwmMode, RetryPolicy, Roboclaw,
+    RoboclawError, S3Mode, Speed, StatusFlags, SwitchMode, VelocityPid,
+};
+
+type Port = Roboclaw<Loopback>;
+
+/// Runs `command` against a device that answers with `responses` in turn,
+/// returning the requests it got and the command's result.
+fn run<R>(
+    responses: &[&[u8]],
+    command: impl FnOnce(&mut Port) -> Result<R, RoboclawError>,
+) -> (Vec<Vec<u8>>, R) {
+    let (host, device) = Loopback::pair();
+    let mut roboclaw = Roboclaw::new(host).with_retry_policy(RetryPolicy::none());
+    let requests = respond(device, responses.iter().map(|r| r.to_vec()).collect());
+    let result = command(&mut roboclaw).unwrap();
+    (requests.join().unwrap(), result)
+}
+
+/// Checks that `command` sends exactly `packet` followed by its CRC.
+fn assert_write(packet: &[u8], command: impl FnOnce(&mut Port) -> Result<(), RoboclawError>) {
+    let (requests, ()) = run(&[&[0xFF]], command);
+    assert_eq!(requests, [with_crc(packet)], "command {}", packet[1]);
+}
+
+/// Checks that `command` sends a bare read of `code`, and returns what it
+/// made of `response`.
+fn read<R>(
+    code: u8,
+    response: &[u8],
+    command: impl FnOnce(&mut Port) -> Result<R, RoboclawError>,
+) -> R {
+    let (requests, result) = run(&[response], command);
+    assert_eq!(requests, [vec![0x80, code]], "command {}", code);
+    result
+}
+
+#[test]
+fn address_is_sent_once() {
+    let (requests, ()) = run(&[&[0xFF]], |r| r.reset_encoders());
+    assert_eq!(requests, [vec![0x80, 20, 0x49, 0x2D]]);
+
+    let (requests, ()) = run(&[&[0xFF]], |r| r.forward(Motor::M1, 64));
+    assert_eq!(requests, [vec![0x80, 0, 64, 0x73, 0x9E]]);
+
+    let (host, device) = Loopback::pair();
+    let requests = respond(device, vec![vec![0xFF]]);
+    Roboclaw::with_address(host, 0x85).reset_encoders().unwrap();
+    assert_eq!(requests.join().unwrap(), [with_crc(&[0x85, 20])]);
+}
+
+#[test]
+fn compatibility_commands() {
+    assert_write(&[0x80, 0, 10], |r| r.forward_m1(10));
+    assert_write(&[0x80, 1, 11], |r| r.backward_m1(11));
+    assert_write(&[0x80, 4, 12], |r| r.forward_m2(12));
+    assert_write(&[0x80, 5, 13], |r| r.backward_m2(13));
+    assert_write(&[0x80, 6, 96], |r| r.forward_backward_m1(96));
+    assert_write(&[0x80, 7, 32], |r| r.forward_backward_m2(32));
+    assert_write(&[0x80, 8, 20], |r| r.forward_mixed(20));
+    assert_write(&[0x80, 9, 21], |r| r.backward_mixed(21));
+    assert_write(&[0x80, 10, 22], |r| r.turn_right_mixed(22));
+    assert_write(&[0x80, 11, 23], |r| r.turn_left_mixed(23));
+    assert_write(&[0x80, 12, 80], |r| r.forward_backward_mixed(80));
+    assert_write(&[0x80, 13, 48], |r| r.left_right_mixed(48));
+}
+
+#[test]
+fn encoder_commands() {
+    assert_write(&[0x80, 20], |r| r.reset_encoders());
+    assert_write(&[0x80, 22, 0x00, 0x00, 0x12, 0x34], |r| {
+        r.set_encoder(Motor::M1, 0x1234)
+    });
+    assert_write(&[0x80, 23, 0xFF, 0xFF, 0xFF, 0xFE], |r| {
+        r.set_encoder(Motor::M2, -2)
+    });
+
+    assert_eq!(
+        read(16, &[0xFF, 0xFF, 0xFF, 0xF6, 0x03], |r| r
+            .read_encoder(Motor::M1)),
+        Encoder {
+            count: -10,
+            underflow: true,
+            overflow: false,
+            direction: Direction::Backward
+        }
+    );
+    assert_eq!(
+        read(17, &[0x00, 0x00, 0x01, 0x00, 0x04], |r| r
+            .read_encoder(Motor::M2)),
+        Encoder {
+            count: 256,
+            underflow: false,
+            overflow: true,
+            direction: Direction::Forward
+        }
+    );
+    assert_eq!(
+        read(78, &[0x00, 0x00, 0x00, 0x01, 0xFF, 0xFF, 0xFF, 0xFF], |r| r
+            .read_encoders()),
+        (1, u32::MAX)
+    );
+}
+
+#[test]
+fn speed_reads() {
+    // Magnitude with the direction in the status byte, and signed.
+    assert_eq!(
+        read(18, &[0x00, 0x00, 0x03, 0xE8, 0x01], |r| r
+            .read_speed(Motor::M1)),
+        Speed {
+            qpps: -1000,
+            direction: Direction::Backward
+        }
+    );
+    assert_eq!(
+        read(19, &[0x00, 0x00, 0x03, 0xE8, 0x00], |r| r
+            .read_speed(Motor::M2)),
+        Speed {
+            qpps: 1000,
+            direction: Direction::Forward
+        }
+    );
+    assert_eq!(
+        read(30, &[0xFF, 0xFF, 0xFC, 0x18, 0x01], |r| r
+            .read_instantaneous_speed(Motor::M1))
+        .qpps,
+        -1000
+    );
+    assert_eq!(
+        read(31, &[0x00, 0x00, 0x00, 0x05, 0x00], |r| r
+            .read_instantaneous_speed(Motor::M2))
+        .qpps,
+        5
+    );
+    assert_eq!(
+        read(79, &[0x00, 0x00, 0x00, 0x07, 0xFF, 0xFF, 0xFF, 0xF9], |r| r
+            .read_instantaneous_speeds()),
+        (7, -7)
+    );
+}
+
+#[test]
+fn version_read() {
+    let version = read(21, b"USB Roboclaw 2x60a v4.1.34\n\0", |r| r.read_version());
+    assert_eq!(version.text, "USB Roboclaw 2x60a v4.1.34");
+
+    // The second temperature sensor is only read once the model is known.
+    let (requests, temperature) = run(&[b"Roboclaw 2x60a v4.1.34\n\0", &[0x01, 0x2C]], |r| {
+        r.read_temperature2()
+    });
+    assert_eq!(requests, [vec![0x80, 21], vec![0x80, 83]]);
+    assert_eq!(temperature, 30.0);
+}
+
+#[test]
+fn battery_and_status_reads() {
+    assert_eq!(
+        read(24, &[0x00, 0x7B], |r| r.read_main_battery_voltage()),
+        12.3
+    );
+    assert_eq!(
+        read(25, &[0x00, 0x32], |r| r.read_logic_battery_voltage()),
+        5.0
+    );
+    assert_eq!(
+        read(59, &[0x00, 0x69, 0x00, 0xA8], |r| r
+            .read_min_max_main_voltages()),
+        (10.5, 16.8)
+    );
+    assert_eq!(
+        read(60, &[0x00, 0x46, 0x01, 0x2C], |r| r
+            .read_min_max_logic_voltages()),
+        (7.0, 30.0)
+    );
+    assert_eq!(
+        read(48, &[0x40, 0x00, 0xC0, 0x00], |r| r.read_pwms()),
+        (16384, -16384)
+    );
+    assert_eq!(
+        read(49, &[0x01, 0x90, 0xFF, 0xF6], |r| r.read_currents()),
+        (4.0, -0.1)
+    );
+    assert_eq!(read(82, &[0x01, 0x01], |r| r.read_temperature()), 25.7);
+    assert_eq!(
+        read(90, &[0x01, 0x00, 0x00, 0x01], |r| r.read_error()),
+        StatusFlags::E_STOP | StatusFlags::SPEED_ERROR_LIMIT_WARNING
+    );
+    assert_eq!(
+        read(90, &[0x00, 0x00, 0xC0, 0x01], |r| r.read_error()),
+        StatusFlags::E_STOP
+    );
+    assert_eq!(
+        read(47, &[0x80, 0x03], |r| r.read_buffers()),
+        (BufferStatus::Empty, BufferStatus::NotEmpty(3))
+    );
+}
+
+#[test]
+fn voltage_limits() {
+    assert_write(&[0x80, 2, 20], |r| r.set_min_voltage_main_battery(10.0));
+    assert_write(&[0x80, 3, 154], |r| r.set_max_voltage_main_battery(30.0));
+    assert_write(&[0x80, 26, 5], |r| r.set_min_voltage_logic_battery(7.0));
+    assert_write(&[0x80, 27, 64], |r| r.set_max_voltage_logic_battery(12.5));
+    assert_write(&[0x80, 57, 0x00, 0x69, 0x00, 0xA8], |r| {
+        r.set_main_voltages(10.5, 16.8)
+    });
+    assert_write(&[0x80, 58, 0x00, 0x46, 0x01, 0x2C], |r| {
+        r.set_logic_voltages(7.0, 30.0)
+    });
+}
+
+#[test]
+fn pid_commands() {
+    let velocity = VelocityPid {
+        kp: 1.0,
+        ki: 0.5,
+        kd: 0.25,
+        qpps: 1000,
+    };
+    let velocity_bytes = [
+        0x00, 0x01, 0x00, 0x00, // kp
+        0x00, 0x00, 0x80, 0x00, // ki
+        0x00, 0x00, 0x40, 0x00, // kd
+        0x00, 0x00, 0x03, 0xE8, // qpps
+    ];
+    assert_write(
+        &[
+            0x80, 28, // address and command
+            0x00, 0x00, 0x40, 0x00, // kd
+            0x00, 0x01, 0x00, 0x00, // kp
+            0x00, 0x00, 0x80, 0x00, // ki
+            0x00, 0x00, 0x03, 0xE8, // qpps
+        ],
+        |r| r.set_velocity_pid(Motor::M1, velocity),
+    );
+    assert_eq!(
+        read(56, &velocity_bytes, |r| r.read_velocity_pid(Motor::M2)),
+        velocity
+    );
+
+    let position = PositionPid {
+        kp: 2.0,
+        ki: 0.5,
+        kd: 8.0,
+        ki_max: 100,
+        deadzone: 3,
+        min: -1000,
+        max: 1000,
+    };
+    assert_write(
+        &[
+            0x80, 62, // address and command
+            0x00, 0x00, 0x20, 0x00, // kd
+            0x00, 0x00, 0x08, 0x00, // kp
+            0x00, 0x00, 0x02, 0x00, // ki
+            0x00, 0x00, 0x00, 0x64, // ki_max
+            0x00, 0x00, 0x00, 0x03, // deadzone
+            0xFF, 0xFF, 0xFC, 0x18, // min
+            0x00, 0x00, 0x03, 0xE8, // max
+        ],
+        |r| r.set_position_pid(Motor::M2, position),
+    );
+    assert_eq!(
+        read(
+            63,
+            &[
+                0x00, 0x00, 0x08, 0x00, // kp
+                0x00, 0x00, 0x02, 0x00, // ki
+                0x00, 0x00, 0x20, 0x00, // kd
+                0x00, 0x00, 0x00, 0x64, // ki_max
+                0x00, 0x00, 0x00, 0x03, // deadzone
+                0xFF, 0xFF, 0xFC, 0x18, // min
+                0x00, 0x00, 0x03, 0xE8, // max
+            ],
+            |r| r.read_position_pid(Motor::M1)
+        ),
+        position
+    );
+}
+
+#[test]
+fn duty_and_speed_commands() {
+    assert_write(&[0x80, 32, 0xFF, 0xFF], |r| r.duty_m1(-1));
+    assert_write(&[0x80, 33, 0x12, 0x34], |r| r.duty_m2(0x1234));
+    assert_write(&[0x80, 34, 0x01, 0x02, 0xFE, 0xFE], |r| {
+        r.duty_m1_m2(0x0102, -0x0102)
+    });
+    assert_write(&[0x80, 35, 0x01, 0x02, 0x03, 0x04], |r| {
+        r.speed(Motor::M1, 0x0102_0304)
+    });
+    assert_write(&[0x80, 36, 0xFF, 0xFF, 0xFF, 0x9C], |r| {
+        r.speed(Motor::M2, -100)
+    });
+    assert_write(
+        &[0x80, 37, 0x00, 0x00, 0x00, 0x01, 0xFF, 0xFF, 0xFF, 0xFF],
+        |r| r.speed_m1_m2(1, -1),
+    );
+    assert_write(
+        &[0x80, 38, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x10],
+        |r| r.speed_accel(Motor::M1, 256, 16),
+    );
+    assert_write(
+        &[0x80, 39, 0x00, 0x00, 0x00, 0x01, 0xFF, 0xFF, 0xFF, 0xF0],
+        |r| r.speed_accel(Motor::M2, 1, -16),
+    );
+    assert_write(
+        &[
+            0x80, 40, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x04,
+        ],
+        |r| r.speed_accel_m1_m2(2, 3, 4),
+    );
+    assert_write(
+        &[
+            0x80, 50, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x03, 0x00,
+            0x00, 0x00, 0x04,
+        ],
+        |r| r.speed_accel_m1_m2_2(1, 2, 3, 4),
+    );
+    assert_write(&[0x80, 52, 0x40, 0x00, 0x00, 0x00, 0x00, 0x05], |r| {
+        r.duty_accel(Motor::M1, 0x4000, 5)
+    });
+    assert_write(&[0x80, 53, 0xC0, 0x00, 0x00, 0x00, 0x00, 0x06], |r| {
+        r.duty_accel(Motor::M2, -0x4000, 6)
+    });
+    assert_write(
+        &[
+            0x80, 54, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x03, 0x00, 0x00, 0x00, 0x04,
+        ],
+        |r| r.duty_accel_m1_m2(1, 2, 3, 4),
+    );
+}
+
+#[test]
+fn buffered_commands() {
+    assert_write(
+        &[
+            0x80, 41, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x20, 0x00,
+        ],
+        |r| r.speed_distance_m1(16, 32, Buffer::Queue),
+    );
+    assert_write(
+        &[
+            0x80, 42, 0xFF, 0xFF, 0xFF, 0xF0, 0x00, 0x00, 0x00, 0x20, 0x01,
+        ],
+        |r| r.speed_distance_m2(-16, 32, Buffer::Immediate),
+    );
+    assert_write(
+        &[
+            0x80, 43, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x03, 0x00,
+            0x00, 0x00, 0x04, 0x00,
+        ],
+        |r| r.speed_distance_m1_m2(1, 2, 3, 4, Buffer::Queue),
+    );
+    assert_write(
+        &[
+            0x80, 44, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x03, 0x01,
+        ],
+        |r| r.speed_accel_distance(Motor::M1, 1, 2, 3, Buffer::default()),
+    );
+    assert_write(
+        &[
+            0x80, 45, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x03, 0x00,
+        ],
+        |r| r.speed_accel_distance(Motor::M2, 1, 2, 3, Buffer::Queue),
+    );
+    assert_write(
+        &[
+            0x80, 46, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x03, 0x00,
+            0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x05, 0x00,
+        ],
+        |r| r.speed_accel_distance_m1_m2(1, 2, 3, 4, 5, Buffer::Queue),
+    );
+    assert_write(
+        &[
+            0x80, 65, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x03, 0x00,
+            0x00, 0x00, 0x04, 0x00,
+        ],
+        |r| r.speed_accel_deccel_position(Motor::M1, 1, 2, 3, 4, Buffer::Queue),
+    );
+    assert_write(
+        &[
+            0x80, 66, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x03, 0x00,
+            0x00, 0x00, 0x04, 0x01,
+        ],
+        |r| r.speed_accel_deccel_position(Motor::M2, 1, 2, 3, 4, Buffer::Immediate),
+    );
+    assert_write(
+        &[
+            0x80, 67, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x03, 0x00,
+            0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x06, 0x00, 0x00, 0x00,
+            0x07, 0x00, 0x00, 0x00, 0x08, 0x00,
+        ],
+        |r| r.speed_accel_deccel_position_m1_m2(1, 2, 3, 4, 5, 6, 7, 8, Buffer::Queue),
+    );
+}
+
+#[test]
+fn settings_commands() {
+    assert_write(&[0x80, 68, 0x00, 0x00, 0x03, 0xE8], |r| {
+        r.set_default_accel(Motor::M1, 1000)
+    });
+    assert_write(&[0x80, 69, 0x00, 0x01, 0x00, 0x00], |r| {
+        r.set_default_accel(Motor::M2, 0x10000)
+    });
+
+    let functions = PinFunctions {
+        s3: S3Mode::EStop,
+        s4: SwitchMode::Home,
+        s5: SwitchMode::LimitReverse,
+    };
+    assert_write(&[0x80, 74, 2, 4, 6], |r| r.set_pin_functions(functions));
+    assert_eq!(read(75, &[2, 4, 6], |r| r.read_pin_functions()), functions);
+
+    assert_write(&[0x80, 76, 15, 30], |r| r.set_deadband(1.5, 3.0));
+    assert_eq!(read(77, &[15, 30], |r| r.read_deadband()), (1.5, 3.0));
+
+    assert_write(&[0x80, 92, 0x81], |r| {
+        r.set_encoder_mode(Motor::M1, EncoderMode::ABSOLUTE | EncoderMode::RC_ANALOG)
+    });
+    assert_write(&[0x80, 93, 0x40], |r| {
+        r.set_encoder_mode(Motor::M2, EncoderMode::REVERSE_ENCODER)
+    });
+    assert_eq!(
+        read(91, &[0x00, 0x20], |r| r.read_encoder_modes()),
+        (EncoderMode::empty(), EncoderMode::REVERSE_MOTOR)
+    );
+
+    let config = Config {
+        control_mode: ControlMode::PacketSerial {
+            baud_rate: BaudRate::Baud115200,
+            address: 0x82,
+        },
+        battery_mode: BatteryMode::Auto,
+        slave_mode: false,
+        relay_mode: false,
+        swap_encoders: false,
+        swap_buttons: false,
+        multi_unit_mode: true,
+    };
+    assert_write(&[0x80, 98, 0x82, 0xA7], |r| r.set_config(&config));
+    assert_eq!(read(99, &[0x82, 0xA7], |r| r.get_config()), config);
+
+    assert_write(
+        &[0x80, 133, 0x00, 0x00, 0x01, 0xA9, 0x00, 0x00, 0x00, 0x00],
+        |r| r.set_max_current(Motor::M1, 4.25),
+    );
+    assert_write(
+        &[0x80, 134, 0x00, 0x00, 0x05, 0xDC, 0x00, 0x00, 0x00, 0x00],
+        |r| r.set_max_current(Motor::M2, 15.0),
+    );
+    assert_eq!(
+        read(
+            135,
+            &[0x00, 0x00, 0x01, 0xA9, 0x00, 0x00, 0x00, 0x00],
+            |r| r.read_max_current(Motor::M1)
+        ),
+        4.25
+    );
+    assert_eq!(
+        read(
+            136,
+            &[0x00, 0x00, 0x05, 0xDC, 0x00, 0x00, 0x00, 0x00],
+            |r| r.read_max_current(Motor::M2)
+        ),
+        15.0
+    );
+
+    assert_write(&[0x80, 148, 0], |r| {
+        r.set_pwm_mode(PwmMode::LockedAntiphase)
+    });
+    assert_eq!(
+        read(149, &[1], |r| r.read_pwm_mode()),
+        PwmMode::SignMagnitude
+    );
+}
+
+#[test]
+fn nvm_commands() {
+    assert_write(&[0x80, 80], |r| {
+        r.restore_defaults(Confirm::overwrite_settings())
+    });
+    assert_write(&[0x80, 94, 0xE2, 0x2E, 0xAB, 0x7A], |r| {
+        r.write_nvm(Confirm::overwrite_settings())
+    });
+    assert_write(&[0x80, 95], |r| r.read_nvm());
+}